@@ -1,5 +1,6 @@
-use crate::{Enemy, Map, Name, Position, Viewshed, WantsToMelee};
-use rltk::{console, field_of_view, Point};
+use crate::status_effect_system::confused_step;
+use crate::{Enemy, EntityMoved, LastPath, Map, Name, Position, StatusEffect, StatusEffectKind, TileType, Viewshed, WantsToMelee};
+use rltk::{console, Point, RandomNumberGenerator as RNG};
 use specs::prelude::*;
 
 pub struct EnemyAI {}
@@ -16,6 +17,10 @@ impl<'a> System<'a> for EnemyAI {
         WriteStorage<'a, Position>,
         WriteStorage<'a, WantsToMelee>,
         Entities<'a>,
+        ReadStorage<'a, StatusEffect>,
+        WriteExpect<'a, RNG>,
+        WriteStorage<'a, EntityMoved>,
+        WriteStorage<'a, LastPath>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -29,33 +34,113 @@ impl<'a> System<'a> for EnemyAI {
             mut position,
             mut wants_to_melee,
             entities,
+            status_effects,
+            mut rng,
+            mut moved,
+            mut last_paths,
         ) = data;
 
-        for (mut viewshed, _enemy, name, mut pos, entity) in
-            (&mut viewshed, &enemy, &name, &mut position, &entities).join()
+        // Positions are only borrowed mutably on an actual move, so idle
+        // monsters don't show up as changed in the map index
+        for (viewshed, _enemy, name, entity) in
+            (&mut viewshed, &enemy, &name, &entities).join()
         {
-            let distance = rltk::DistanceAlg::Pythagoras.distance2d(Point::new(pos.x, pos.y), *player_pos);
+            let pos = match position.get(entity) {
+                Some(pos) => Point::new(pos.x, pos.y),
+                None => continue,
+            };
+            // Only a path planned this turn is worth showing
+            last_paths.remove(entity);
+            if StatusEffect::has(&status_effects, entity, StatusEffectKind::Stun) {
+                continue;
+            }
+            if StatusEffect::has(&status_effects, entity, StatusEffectKind::Confusion) {
+                let (dx, dy) = confused_step(&mut rng);
+                let (x, y) = (pos.x + dx, pos.y + dy);
+                if x > 0 && x < map.width - 1 && y > 0 && y < map.height - 1 {
+                    let idx = map.xy_idx(x, y);
+                    if !map.is_blocked(idx) {
+                        let pos = position.get_mut(entity).unwrap();
+                        pos.x = x;
+                        pos.y = y;
+                        map.move_entity(entity, idx);
+                        viewshed.dirty = true;
+                        moved.insert(entity, EntityMoved {}).expect("Unable to insert marker");
+                    }
+                }
+                continue;
+            }
+            let distance = rltk::DistanceAlg::Pythagoras.distance2d(pos, *player_pos);
             if distance < 1.5 {
                 // Attack goes here
-                console::log(&format!("{} wants to melee player", name.name));
+                console::log(format!("{} wants to melee player", name.name));
                 wants_to_melee.insert(entity, WantsToMelee{ target: *player_entity }).expect("Unable to insert attack");
             } else if viewshed.visible_tiles.contains(&*player_pos) {
-                console::log(&format!("{} pathes to player", name.name));
-                let path = rltk::a_star_search(
-                    map.xy_idx(pos.x, pos.y) as i32,
-                    map.xy_idx(player_pos.x, player_pos.y) as i32,
-                    &mut *map,
-                );
-                if path.success && path.steps.len() > 1 {
-                    let mut idx = map.xy_idx(pos.x, pos.y);
-                    map.blocked[idx] = false;
+                console::log(format!("{} pathes to player", name.name));
+                let (start, end) = (map.xy_idx(pos.x, pos.y), map.xy_idx(player_pos.x, player_pos.y));
+                let path = map.path_to(start, end);
+                last_paths.insert(entity, LastPath { steps: path.steps.clone() }).expect("Unable to record path");
+                if path.success && path.steps.len() > 1 && map.tiles[path.steps[1]] == TileType::DoorClosed {
+                    map.open_door(path.steps[1]);
+                    viewshed.dirty = true;
+                } else if path.success && path.steps.len() > 1 {
+                    let pos = position.get_mut(entity).unwrap();
                     pos.x = path.steps[1] as i32 % map.width;
                     pos.y = path.steps[1] as i32 / map.width;
-                    idx = map.xy_idx(pos.x, pos.y);
-                    map.blocked[idx] = true;
+                    map.move_entity(entity, path.steps[1]);
                     viewshed.dirty = true;
+                    moved.insert(entity, EntityMoved {}).expect("Unable to insert marker");
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_path_only_lasts_while_pathing() {
+        let mut ecs = World::new();
+        ecs.register::<Viewshed>();
+        ecs.register::<Enemy>();
+        ecs.register::<Name>();
+        ecs.register::<Position>();
+        ecs.register::<WantsToMelee>();
+        ecs.register::<StatusEffect>();
+        ecs.register::<EntityMoved>();
+        ecs.register::<LastPath>();
+        ecs.insert(Map::filled(20, 20, TileType::Floor));
+        ecs.insert(RNG::seeded(1));
+        let player = ecs.create_entity().with(Position { x: 10, y: 5 }).build();
+        ecs.insert(player);
+        ecs.insert(Point::new(10, 5));
+        let mut viewshed = Viewshed::new(8);
+        viewshed.visible_tiles.push(Point::new(10, 5));
+        let monster = ecs
+            .create_entity()
+            .with(Position { x: 5, y: 5 })
+            .with(viewshed)
+            .with(Enemy {})
+            .with(Name { name: "Goblin".to_string() })
+            .build();
+
+        EnemyAI {}.run_now(&ecs);
+        assert!(ecs.read_storage::<LastPath>().contains(monster));
+
+        // Next to the player it attacks instead
+        *ecs.write_storage::<Position>().get_mut(monster).unwrap() = Position { x: 9, y: 5 };
+        EnemyAI {}.run_now(&ecs);
+        assert!(ecs.read_storage::<WantsToMelee>().contains(monster));
+        assert!(!ecs.read_storage::<LastPath>().contains(monster));
+
+        // Back out of reach it paths again, until it loses sight of the player
+        *ecs.write_storage::<Position>().get_mut(monster).unwrap() = Position { x: 5, y: 5 };
+        EnemyAI {}.run_now(&ecs);
+        assert!(ecs.read_storage::<LastPath>().contains(monster));
+        ecs.write_storage::<Viewshed>().get_mut(monster).unwrap().visible_tiles.clear();
+        EnemyAI {}.run_now(&ecs);
+        assert!(!ecs.read_storage::<LastPath>().contains(monster));
+    }
+}
@@ -1,6 +1,11 @@
-use super::{CombatStats, Name, SufferDamage, WantsToMelee, Player};
+use super::{
+    CombatStats, Equipped, Experience, ExperienceValue, GameLog, GodMode, InBackpack, InflictsStatus,
+    LastAttacker, LootTable, MeleeWeapon, Name, Player, Position, StatusEffect, SufferDamage, WantsToMelee,
+};
+use crate::spawner;
+use crate::status_effect_system::inflict_status;
 use specs::prelude::*;
-use rltk::{console};
+use rltk::{console, RandomNumberGenerator as RNG};
 
 pub struct MeleeCombatSystem {}
 
@@ -11,12 +16,30 @@ impl<'a> System<'a> for MeleeCombatSystem {
         ReadStorage<'a, Name>,
         ReadStorage<'a, CombatStats>,
         WriteStorage<'a, SufferDamage>,
+        WriteExpect<'a, RNG>,
+        WriteExpect<'a, GameLog>,
+        ReadStorage<'a, InflictsStatus>,
+        WriteStorage<'a, StatusEffect>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, MeleeWeapon>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, mut wants_melee, names, combat_stats, mut inflict_damage) = data;
+        let (
+            entities,
+            mut wants_melee,
+            names,
+            combat_stats,
+            mut inflict_damage,
+            mut rng,
+            mut log,
+            inflicts_status,
+            mut status_effects,
+            equipped,
+            melee_weapons,
+        ) = data;
 
-        for (_entity, wants_melee, name, stats) in
+        for (entity, wants_melee, name, stats) in
             (&entities, &wants_melee, &names, &combat_stats).join()
         {
             if stats.hp > 0 {
@@ -24,19 +47,49 @@ impl<'a> System<'a> for MeleeCombatSystem {
                 if target_stats.hp > 0 {
                     let target_name = names.get(wants_melee.target).unwrap();
 
-                    let damage = i32::max(0, stats.power - target_stats.defense);
-
-                    if damage == 0 {
-                        console::log(&format!(
-                            "{} is unable to hurt {}",
+                    let natural_roll = rng.roll_dice(1, 20);
+                    if natural_roll == 1 {
+                        log.log(format!(
+                            "{} fumbles the attack on {}.",
                             &name.name, &target_name.name
                         ));
+                    } else if natural_roll == 20 || natural_roll + stats.skill >= target_stats.armor_class {
+                        let dice = (&equipped, &melee_weapons)
+                            .join()
+                            .find(|(eq, _)| eq.owner == entity)
+                            .map_or(stats.damage, |(_, weapon)| weapon.damage);
+                        let mut damage = rng.roll(dice);
+                        if natural_roll == 20 {
+                            // Criticals roll the damage dice twice
+                            damage += rng.roll(dice);
+                        }
+                        let damage = i32::max(1, damage);
+                        log.log(format!(
+                            "{} {} {}, for {} hp.",
+                            &name.name,
+                            if natural_roll == 20 { "critically hits" } else { "hits" },
+                            &target_name.name,
+                            damage
+                        ));
+                        SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage, Some(entity));
+                        if let Some(inflicts) = inflicts_status.get(entity) {
+                            inflict_status(
+                                &entities,
+                                &mut status_effects,
+                                wants_melee.target,
+                                inflicts.kind,
+                                inflicts.turns,
+                            );
+                            log.log(format!(
+                                "{} is {}.",
+                                &target_name.name, inflicts.kind.adjective()
+                            ));
+                        }
                     } else {
-                        console::log(&format!(
-                            "{} hits {}, for {} hp.",
-                            &name.name, &target_name.name, damage
+                        log.log(format!(
+                            "{} misses {}.",
+                            &name.name, &target_name.name
                         ));
-                        SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage);
                     }
                 }
             }
@@ -50,17 +103,24 @@ pub struct DamageSystem {}
 
 impl<'a> System<'a> for DamageSystem {
     type SystemData = (
+        Entities<'a>,
         WriteStorage<'a, CombatStats>,
         WriteStorage<'a, SufferDamage>,
+        ReadStorage<'a, GodMode>,
+        WriteStorage<'a, LastAttacker>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut stats, mut damage) = data;
+        let (entities, mut stats, mut damage, god_mode, mut last_attackers) = data;
 
-        for (mut stats, damage) in (&mut stats, &damage).join() {
-            stats.hp -= damage.amount.iter().sum::<i32>();
+        for (entity, stats, damage) in (&entities, &mut stats, &damage).join() {
+            if !god_mode.contains(entity) {
+                stats.hp -= damage.amount.iter().sum::<i32>();
+            }
+            if let Some(attacker) = damage.last_attacker {
+                last_attackers.insert(entity, LastAttacker { attacker }).expect("Unable to insert attacker");
+            }
         }
-
         damage.clear();
     }
 }
@@ -82,9 +142,118 @@ impl DamageSystem {
                 }
             }
         }
-    
+
+        DamageSystem::award_experience(ecs, &dead);
+
         for victim in dead {
+            DamageSystem::leave_remains(ecs, victim);
             ecs.delete_entity(victim).expect("Unable to delete");
         }
     }
+
+    /// Drops everything the victim carried or rolled from its loot table,
+    /// and leaves a corpse in its place.
+    fn leave_remains(ecs : &mut World, victim : Entity) {
+        const CORPSE_DECAY : i32 = 50;
+
+        let (x, y, name) = {
+            let positions = ecs.read_storage::<Position>();
+            let names = ecs.read_storage::<Name>();
+            match (positions.get(victim), names.get(victim)) {
+                (Some(pos), Some(name)) => (pos.x, pos.y, name.name.clone()),
+                _ => return
+            }
+        };
+        ecs.write_resource::<GameLog>().log(format!("{} dies.", name));
+
+        {
+            let entities = ecs.entities();
+            let mut backpack = ecs.write_storage::<InBackpack>();
+            let mut equipped = ecs.write_storage::<Equipped>();
+            let mut positions = ecs.write_storage::<Position>();
+            let mut carried : Vec<Entity> = Vec::new();
+            for (item, pack) in (&entities, &backpack).join() {
+                if pack.owner == victim { carried.push(item); }
+            }
+            for (item, eq) in (&entities, &equipped).join() {
+                if eq.owner == victim { carried.push(item); }
+            }
+            for item in carried {
+                backpack.remove(item);
+                equipped.remove(item);
+                positions.insert(item, Position { x, y }).expect("Unable to drop item");
+            }
+        }
+
+        let drops : Vec<&spawner::ItemTemplate> = {
+            let tables = ecs.read_storage::<LootTable>();
+            let mut rng = ecs.write_resource::<RNG>();
+            match tables.get(victim) {
+                Some(table) => table.drops.iter()
+                    .filter(|(_, chance)| rng.roll_dice(1, 100) <= *chance)
+                    .filter_map(|(item, _)| spawner::item_template(item))
+                    .collect(),
+                None => Vec::new()
+            }
+        };
+        for template in drops {
+            spawner::spawn_item(ecs, template, x, y);
+        }
+
+        spawner::spawn_corpse(ecs, &name, x, y, CORPSE_DECAY);
+    }
+
+    fn award_experience(ecs : &mut World, dead : &[Entity]) {
+        let last_attackers = ecs.read_storage::<LastAttacker>();
+        let values = ecs.read_storage::<ExperienceValue>();
+        let names = ecs.read_storage::<Name>();
+        let mut experience = ecs.write_storage::<Experience>();
+        let mut combat_stats = ecs.write_storage::<CombatStats>();
+        let mut log = ecs.write_resource::<GameLog>();
+
+        for victim in dead.iter() {
+            let killer = last_attackers.get(*victim).map(|last| last.attacker);
+            if let (Some(killer), Some(value)) = (killer, values.get(*victim)) {
+                if let Some(exp) = experience.get_mut(killer) {
+                    let levels = exp.gain(value.xp);
+                    if let Some(name) = names.get(killer) {
+                        log.log(format!("{} gains {} xp.", name.name, value.xp));
+                        if levels > 0 {
+                            log.log(format!("{} reaches level {}!", name.name, exp.level));
+                        }
+                    }
+                    if let Some(stats) = combat_stats.get_mut(killer) {
+                        stats.max_hp += 5 * levels;
+                        stats.hp += 5 * levels;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn damage_is_applied_once_and_the_attacker_remembered() {
+        let mut ecs = World::new();
+        ecs.register::<CombatStats>();
+        ecs.register::<SufferDamage>();
+        ecs.register::<GodMode>();
+        ecs.register::<LastAttacker>();
+        let attacker = ecs.create_entity().build();
+        let stats = CombatStats { max_hp: 10, hp: 10, armor_class: 10, skill: 0, damage: rltk::DiceType::new(1, 4, 0) };
+        let victim = ecs.create_entity().with(stats).build();
+        SufferDamage::new_damage(&mut ecs.write_storage(), victim, 3, Some(attacker));
+        SufferDamage::new_damage(&mut ecs.write_storage(), victim, 2, None);
+
+        DamageSystem {}.run_now(&ecs);
+        DamageSystem {}.run_now(&ecs);
+
+        assert_eq!(ecs.read_storage::<CombatStats>().get(victim).unwrap().hp, 5);
+        assert!(!ecs.read_storage::<SufferDamage>().contains(victim));
+        assert_eq!(ecs.read_storage::<LastAttacker>().get(victim).unwrap().attacker, attacker);
+    }
 }
@@ -1,25 +1,32 @@
-use rltk::{RGB};
+use rltk::{DiceType, RGB};
 // use rltk::RandomNumberGenerator as RNG;
 use specs::prelude::*;
 use specs_derive::Component;
+use crate::fov::FovAlgorithm;
+use crate::map::Map;
 // use crate::rect::*;
 
-#[derive(Component)]
+/// Flagged so `MapIndexingSystem` only has to look at entities that moved.
 pub struct Position {
     pub x: i32,
     pub y: i32,
 }
+impl Component for Position {
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
+}
 impl Position {
     pub fn from_tuple((x, y) : (i32, i32)) -> Position {
-        Position { x: x, y: y }
+        Position { x, y }
     }
 }
 
+/// Lower `render_order` is drawn on top when several entities share a tile.
 #[derive(Component)]
 pub struct Renderable {
     pub glyph: rltk::FontCharType,
     pub fg: RGB,
     pub bg: RGB,
+    pub render_order: i32,
 }
 
 #[derive(Component)]
@@ -28,11 +35,11 @@ pub struct LeftMover {}
 pub struct LeftWalker {}
 
 impl<'a> System<'a> for LeftWalker {
-    type SystemData = (ReadStorage<'a, LeftMover>, WriteStorage<'a, Position>);
-    fn run(&mut self, (lefty, mut pos) : Self::SystemData) {
+    type SystemData = (ReadExpect<'a, Map>, ReadStorage<'a, LeftMover>, WriteStorage<'a, Position>);
+    fn run(&mut self, (map, lefty, mut pos) : Self::SystemData) {
         for (_lefty, pos) in (&lefty, &mut pos).join() {
             pos.x -= 1;
-            if pos.x <0 { pos.x = 79}
+            if pos.x <0 { pos.x = map.width - 1}
         }
     }
 }
@@ -41,11 +48,15 @@ impl<'a> System<'a> for LeftWalker {
 pub struct Viewshed {
     pub visible_tiles: Vec<rltk::Point>,
     pub range: i32,
-    pub dirty: bool
+    pub dirty: bool,
+    pub algorithm: FovAlgorithm
 }
 impl Viewshed {
     pub fn new(range: i32) -> Viewshed {
-        Viewshed { visible_tiles: Vec::new(), range: range, dirty: true }
+        Viewshed::with_algorithm(range, FovAlgorithm::Rltk)
+    }
+    pub fn with_algorithm(range: i32, algorithm: FovAlgorithm) -> Viewshed {
+        Viewshed { visible_tiles: Vec::new(), range, dirty: true, algorithm }
     }
 }
 
@@ -61,12 +72,15 @@ pub struct Name {
 #[derive(Component, Debug)]
 pub struct Enemy {}
 
+/// Attacks roll 1d20 + `skill` against the target's `armor_class`,
+/// and hits deal `damage` dice worth of hp.
 #[derive(Component, Debug)]
 pub struct CombatStats {
     pub max_hp : i32,
     pub hp : i32,
-    pub defense : i32,
-    pub power : i32
+    pub armor_class : i32,
+    pub skill : i32,
+    pub damage : DiceType
 }
 
 #[derive(Component, Debug, Clone)]
@@ -74,21 +88,290 @@ pub struct WantsToMelee {
     pub target : Entity
 }
 
+/// Damage queued for the next `DamageSystem` run, which removes it once
+/// applied.
 #[derive(Component, Debug)]
 pub struct SufferDamage {
-    pub amount : Vec<i32>
+    pub amount : Vec<i32>,
+    pub last_attacker : Option<Entity>
 }
 
 impl SufferDamage {
-    pub fn new_damage(store: &mut WriteStorage<SufferDamage>, victim: Entity, amount: i32) {
+    pub fn new_damage(store: &mut WriteStorage<SufferDamage>, victim: Entity, amount: i32, attacker: Option<Entity>) {
         if let Some(suffering) = store.get_mut(victim) {
             suffering.amount.push(amount);
+            if attacker.is_some() {
+                suffering.last_attacker = attacker;
+            }
         } else {
-            let dmg = SufferDamage { amount : vec![amount] };
+            let dmg = SufferDamage { amount : vec![amount], last_attacker : attacker };
             store.insert(victim, dmg).expect("Unable to insert damage");
         }
     }
 }
 
+/// Whoever last hurt the entity, so the killer can be credited when it dies.
+#[derive(Component, Debug)]
+pub struct LastAttacker {
+    pub attacker : Entity
+}
+
+#[derive(Component, Debug)]
+pub struct Player {}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum StatusEffectKind {
+    Poison,
+    Confusion,
+    Stun,
+    Regeneration,
+}
+
+impl StatusEffectKind {
+    pub fn adjective(self) -> &'static str {
+        match self {
+            StatusEffectKind::Poison => "poisoned",
+            StatusEffectKind::Confusion => "confused",
+            StatusEffectKind::Stun => "stunned",
+            StatusEffectKind::Regeneration => "regenerating",
+        }
+    }
+    pub fn glyph(self) -> char {
+        match self {
+            StatusEffectKind::Poison => 'P',
+            StatusEffectKind::Confusion => '?',
+            StatusEffectKind::Stun => '*',
+            StatusEffectKind::Regeneration => '+',
+        }
+    }
+    pub fn color(self) -> RGB {
+        match self {
+            StatusEffectKind::Poison => RGB::named(rltk::GREEN),
+            StatusEffectKind::Confusion => RGB::named(rltk::MAGENTA),
+            StatusEffectKind::Stun => RGB::named(rltk::YELLOW),
+            StatusEffectKind::Regeneration => RGB::named(rltk::RED),
+        }
+    }
+    pub fn armor_class_modifier(self) -> i32 {
+        match self {
+            StatusEffectKind::Stun => -4,
+            _ => 0,
+        }
+    }
+    pub fn skill_modifier(self) -> i32 {
+        match self {
+            StatusEffectKind::Poison => -1,
+            StatusEffectKind::Confusion => -2,
+            _ => 0,
+        }
+    }
+}
+
+/// A lingering effect lives on its own entity and points at the `target` it
+/// affects. Stat modifiers are applied to the target's `CombatStats` on the
+/// first tick and reverted when `turns` runs out.
+#[derive(Component, Debug)]
+pub struct StatusEffect {
+    pub target : Entity,
+    pub kind : StatusEffectKind,
+    pub turns : i32,
+    pub applied : bool
+}
+
+impl StatusEffect {
+    pub fn has(store: &ReadStorage<StatusEffect>, target: Entity, kind: StatusEffectKind) -> bool {
+        store.join().any(|e| e.target == target && e.kind == kind)
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct InflictsStatus {
+    pub kind : StatusEffectKind,
+    pub turns : i32
+}
+
+#[derive(Component, Debug)]
+pub struct Experience {
+    pub level : i32,
+    pub xp : i32,
+    pub stat_points : i32
+}
+
+impl Experience {
+    /// Total xp needed to reach `level`.
+    pub fn threshold(level: i32) -> i32 {
+        25 * level * (level - 1)
+    }
+
+    /// Adds `xp` and returns how many levels were gained.
+    pub fn gain(&mut self, xp: i32) -> i32 {
+        self.xp += xp;
+        let mut levels = 0;
+        while self.xp >= Experience::threshold(self.level + 1) {
+            self.level += 1;
+            self.stat_points += 1;
+            levels += 1;
+        }
+        levels
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct ExperienceValue {
+    pub xp : i32
+}
+
+#[derive(Component, Debug)]
+pub struct Item {}
+
+#[derive(Component, Debug, Clone)]
+pub struct InBackpack {
+    pub owner : Entity
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct Equipped {
+    pub owner : Entity
+}
+
+/// Overrides the wielder's `CombatStats::damage` while equipped.
+#[derive(Component, Debug)]
+pub struct MeleeWeapon {
+    pub damage : DiceType
+}
+
+/// Item names and percent chances rolled when the owner dies.
+#[derive(Component, Debug)]
+pub struct LootTable {
+    pub drops : &'static [(String, i32)]
+}
+
+#[derive(Component, Debug)]
+pub struct Corpse {
+    pub decay : i32
+}
+
+/// Fires the trap's effects on any creature that steps onto its tile.
+#[derive(Component, Debug)]
+pub struct EntryTrigger {}
+
+#[derive(Component, Debug)]
+pub struct Hidden {}
+
+#[derive(Component, Debug)]
+pub struct EntityMoved {}
+
+#[derive(Component, Debug)]
+pub struct InflictsDamage {
+    pub damage : DiceType
+}
+
+#[derive(Component, Debug)]
+pub struct Teleports {}
+
+#[derive(Component, Debug)]
+pub struct LightSource {
+    pub radius : i32,
+    pub color : RGB
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum HungerState {
+    WellFed,
+    Normal,
+    Hungry,
+    Starving,
+}
+
+impl HungerState {
+    /// Turns spent in this state before getting hungrier.
+    pub fn duration(self) -> i32 {
+        match self {
+            HungerState::WellFed => 200,
+            HungerState::Normal => 300,
+            HungerState::Hungry => 200,
+            HungerState::Starving => 0,
+        }
+    }
+    pub fn next(self) -> HungerState {
+        match self {
+            HungerState::WellFed => HungerState::Normal,
+            HungerState::Normal => HungerState::Hungry,
+            _ => HungerState::Starving,
+        }
+    }
+    pub fn skill_modifier(self) -> i32 {
+        match self {
+            HungerState::Hungry => -1,
+            HungerState::Starving => -2,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct HungerClock {
+    pub state : HungerState,
+    pub turns : i32
+}
+
+impl HungerClock {
+    /// Switches state, swapping the old state's skill penalty for the new one.
+    pub fn set_state(&mut self, state: HungerState, stats: &mut CombatStats) {
+        stats.skill += state.skill_modifier() - self.state.skill_modifier();
+        self.state = state;
+        self.turns = state.duration();
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct ProvidesFood {}
+
+#[derive(Component, Debug)]
+pub struct ProvidesHealing {
+    pub heal_amount : i32
+}
+
+/// Counts turns towards the next point of natural healing.
+#[derive(Component, Debug)]
+pub struct NaturalRegeneration {
+    pub counter : i32
+}
+
+/// Debug flag: damage is ignored.
+#[derive(Component, Debug)]
+pub struct GodMode {}
+
+/// The A* path a monster last planned, kept for the debug overlay.
 #[derive(Component, Debug)]
-pub struct Player {}
\ No newline at end of file
+pub struct LastPath {
+    pub steps: Vec<usize>
+}
+/// Lists every component once, for registering them all and for the
+/// inspector to find which ones an entity has.
+macro_rules! components {
+    ($($component:ident),* $(,)?) => {
+        pub fn register_components(ecs: &mut World) {
+            $(ecs.register::<$component>();)*
+        }
+
+        /// Names of the components `entity` has, in registration order.
+        pub fn component_names(ecs: &World, entity: Entity) -> Vec<&'static str> {
+            let mut names = Vec::new();
+            $(
+                if ecs.read_storage::<$component>().contains(entity) {
+                    names.push(stringify!($component));
+                }
+            )*
+            names
+        }
+    };
+}
+
+components!(
+    Position, Renderable, LeftMover, Player, Viewshed, Enemy, Name, CombatStats, BlocksTile,
+    SufferDamage, LastAttacker, WantsToMelee, StatusEffect, InflictsStatus, Experience,
+    ExperienceValue, Item, InBackpack, Equipped, MeleeWeapon, LootTable, Corpse, EntryTrigger,
+    Hidden, EntityMoved, InflictsDamage, Teleports, LightSource, HungerClock, ProvidesFood,
+    ProvidesHealing, NaturalRegeneration, GodMode, LastPath,
+);
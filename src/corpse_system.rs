@@ -0,0 +1,17 @@
+use crate::Corpse;
+use specs::prelude::*;
+
+pub struct CorpseDecaySystem {}
+
+impl<'a> System<'a> for CorpseDecaySystem {
+    type SystemData = (Entities<'a>, WriteStorage<'a, Corpse>);
+
+    fn run(&mut self, (entities, mut corpses): Self::SystemData) {
+        for (entity, corpse) in (&entities, &mut corpses).join() {
+            corpse.decay -= 1;
+            if corpse.decay <= 0 {
+                entities.delete(entity).expect("Unable to delete corpse");
+            }
+        }
+    }
+}
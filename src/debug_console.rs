@@ -0,0 +1,179 @@
+use rltk::{Rltk, VirtualKeyCode, RGB};
+
+/// Lines of output kept on screen above the prompt.
+const SCROLLBACK: usize = 10;
+
+pub const HELP: &str = "spawn <name> <x> <y>, give <item>, teleport <x> <y>\nheal, godmode, reveal, regen <generator> [seed]\nvault <name> <x> <y>, save <path>, load <path or fixture>";
+
+pub enum Command {
+    Spawn { name: String, x: i32, y: i32 },
+    Give { name: String },
+    Teleport { x: i32, y: i32 },
+    Heal,
+    GodMode,
+    Reveal,
+    Regen { generator: String, seed: Option<u64> },
+    Vault { name: String, x: i32, y: i32 },
+    Save { path: String },
+    Load { path: String },
+    Help,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |n: usize| -> Result<i32, String> {
+            let word = words.get(n).ok_or(format!("{}: missing argument", words[0]))?;
+            word.parse().map_err(|_| format!("{}: '{}' is not a number", words[0], word))
+        };
+        let command = match words.first() {
+            None => return Err(String::new()),
+            Some(&"spawn") => {
+                // Names can have spaces, so the coordinates are the last two words
+                if words.len() < 4 {
+                    return Err("spawn: expected <name> <x> <y>".to_string());
+                }
+                let n = words.len();
+                Command::Spawn { name: words[1..n - 2].join(" "), x: number(n - 2)?, y: number(n - 1)? }
+            }
+            Some(&"give") => {
+                word(&words, 1)?;
+                Command::Give { name: words[1..].join(" ") }
+            }
+            Some(&"teleport") => Command::Teleport { x: number(1)?, y: number(2)? },
+            Some(&"heal") => Command::Heal,
+            Some(&"godmode") => Command::GodMode,
+            Some(&"reveal") => Command::Reveal,
+            Some(&"regen") => {
+                let seed = match words.get(2) {
+                    Some(seed) => Some(seed.parse().map_err(|_| format!("regen: '{}' is not a seed", seed))?),
+                    None => None,
+                };
+                Command::Regen { generator: word(&words, 1)?, seed }
+            }
+            Some(&"vault") => Command::Vault { name: word(&words, 1)?, x: number(2)?, y: number(3)? },
+            Some(&"save") => Command::Save { path: word(&words, 1)? },
+            Some(&"load") => Command::Load { path: word(&words, 1)? },
+            Some(&"help") => Command::Help,
+            Some(other) => return Err(format!("unknown command '{}', try help", other)),
+        };
+        Ok(command)
+    }
+}
+
+fn word(words: &[&str], n: usize) -> Result<String, String> {
+    words.get(n).map(|w| w.to_string()).ok_or(format!("{}: missing argument", words[0]))
+}
+
+/// Text console for poking at the game while it runs. Only exists in debug
+/// builds or when the game is started with `--debug`.
+#[derive(Default)]
+pub struct DebugConsole {
+    pub open: bool,
+    input: String,
+    history: Vec<String>,
+    /// How far back into `history` Up has gone
+    recalled: Option<usize>,
+    output: Vec<String>,
+}
+
+impl DebugConsole {
+    pub fn print<S: ToString>(&mut self, text: S) {
+        self.output.extend(text.to_string().lines().map(String::from));
+    }
+
+    /// Edits the prompt with `key` and returns the line once it's entered.
+    pub fn handle_key(&mut self, key: VirtualKeyCode, shift: bool) -> Option<String> {
+        match key {
+            VirtualKeyCode::Return => {
+                let line = std::mem::take(&mut self.input);
+                self.recalled = None;
+                if !line.trim().is_empty() {
+                    self.print(format!("> {}", line));
+                    self.history.push(line.clone());
+                    return Some(line);
+                }
+            }
+            VirtualKeyCode::Back => {
+                self.input.pop();
+            }
+            VirtualKeyCode::Up => self.recall(1),
+            VirtualKeyCode::Down => self.recall(-1),
+            key => {
+                if let Some(c) = key_char(key, shift) {
+                    self.input.push(c);
+                }
+            }
+        }
+        None
+    }
+
+    fn recall(&mut self, direction: i32) {
+        if self.history.is_empty() {
+            return;
+        }
+        let back = match (self.recalled, direction) {
+            (None, 1) => 0,
+            (None, _) => return,
+            (Some(0), -1) => {
+                self.recalled = None;
+                self.input.clear();
+                return;
+            }
+            (Some(n), d) => usize::min((n as i32 + d) as usize, self.history.len() - 1),
+        };
+        self.recalled = Some(back);
+        self.input = self.history[self.history.len() - 1 - back].clone();
+    }
+
+    pub fn draw(&self, ctx: &mut Rltk) {
+        let white = RGB::named(rltk::WHITE);
+        let black = RGB::named(rltk::BLACK);
+        ctx.draw_box(0, 0, 79, SCROLLBACK as i32 + 2, white, black);
+        ctx.print_color(2, 0, RGB::named(rltk::YELLOW), black, " Debug console ");
+        let skip = self.output.len().saturating_sub(SCROLLBACK);
+        for (y, line) in (1..).zip(self.output.iter().skip(skip)) {
+            ctx.print(1, y, line);
+        }
+        ctx.print_color(1, SCROLLBACK as i32 + 1, RGB::named(rltk::GREEN), black, format!("> {}_", self.input));
+    }
+}
+
+fn key_char(key: VirtualKeyCode, shift: bool) -> Option<char> {
+    use VirtualKeyCode::*;
+    let letters = [A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z];
+    let digits = [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9];
+    if let Some(n) = letters.iter().position(|k| *k == key) {
+        return Some((b'a' + n as u8) as char);
+    }
+    if let Some(n) = digits.iter().position(|k| *k == key) {
+        return Some((b'0' + n as u8) as char);
+    }
+    match key {
+        Space => Some(' '),
+        Minus if shift => Some('_'),
+        Minus => Some('-'),
+        Period => Some('.'),
+        Slash => Some('/'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawn_takes_names_with_spaces() {
+        match Command::parse("spawn Health Potion 10 12") {
+            Ok(Command::Spawn { name, x, y }) => assert_eq!((name.as_str(), x, y), ("Health Potion", 10, 12)),
+            _ => panic!("spawn didn't parse"),
+        }
+        match Command::parse("spawn goblin 3 4") {
+            Ok(Command::Spawn { name, x, y }) => assert_eq!((name.as_str(), x, y), ("goblin", 3, 4)),
+            _ => panic!("spawn didn't parse"),
+        }
+        assert!(Command::parse("spawn goblin 3").is_err());
+        assert!(Command::parse("spawn Health Potion 3 y").is_err());
+    }
+}
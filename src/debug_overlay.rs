@@ -0,0 +1,88 @@
+use crate::{LastPath, Map};
+use rltk::{DijkstraMap, Point, Rltk, RGB};
+use specs::prelude::*;
+
+/// Map diagnostics drawn over the game, cycled through with a key.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum DebugOverlay {
+    Off,
+    Blocked,
+    TileContent,
+    Paths,
+    Dijkstra,
+    Rooms,
+}
+
+impl DebugOverlay {
+    pub fn next(self) -> DebugOverlay {
+        match self {
+            DebugOverlay::Off => DebugOverlay::Blocked,
+            DebugOverlay::Blocked => DebugOverlay::TileContent,
+            DebugOverlay::TileContent => DebugOverlay::Paths,
+            DebugOverlay::Paths => DebugOverlay::Dijkstra,
+            DebugOverlay::Dijkstra => DebugOverlay::Rooms,
+            DebugOverlay::Rooms => DebugOverlay::Off,
+        }
+    }
+
+    pub fn draw(self, ecs: &World, ctx: &mut Rltk) {
+        let map = ecs.fetch::<Map>();
+        let black = RGB::named(rltk::BLACK);
+        let xy = |idx: usize| (idx as i32 % map.width, idx as i32 / map.width);
+        match self {
+            DebugOverlay::Off => return,
+            DebugOverlay::Blocked => {
+                for idx in 0..map.tiles.len() {
+                    let (x, y) = xy(idx);
+                    let fg = if map.is_blocked(idx) {
+                        RGB::from_f32(0.5, 0.0, 0.0)
+                    } else {
+                        RGB::from_f32(0.0, 0.5, 0.0)
+                    };
+                    ctx.set(x, y, fg, black, rltk::to_cp437('#'));
+                }
+            }
+            DebugOverlay::TileContent => {
+                for idx in 0..map.tiles.len() {
+                    let count = map.entities_at(idx).len();
+                    if count > 0 {
+                        let (x, y) = xy(idx);
+                        let glyph = std::char::from_digit(count as u32, 10).unwrap_or('+');
+                        ctx.set(x, y, RGB::named(rltk::CYAN), black, rltk::to_cp437(glyph));
+                    }
+                }
+            }
+            DebugOverlay::Paths => {
+                let paths = ecs.read_storage::<LastPath>();
+                for path in paths.join() {
+                    for idx in path.steps.iter().skip(1) {
+                        let (x, y) = xy(*idx);
+                        ctx.set_bg(x, y, RGB::named(rltk::PURPLE));
+                    }
+                }
+            }
+            DebugOverlay::Dijkstra => {
+                const MAX_DEPTH: f32 = 100.0;
+                let player_pos = ecs.fetch::<Point>();
+                let start = [map.xy_idx(player_pos.x, player_pos.y)];
+                let dijkstra = DijkstraMap::new(map.width, map.height, &start, &*map, MAX_DEPTH);
+                for (idx, distance) in dijkstra.map.iter().enumerate() {
+                    if *distance < MAX_DEPTH {
+                        let (x, y) = xy(idx);
+                        let heat = 1.0 - distance / MAX_DEPTH;
+                        ctx.set_bg(x, y, RGB::from_f32(heat, 0.0, 1.0 - heat));
+                    }
+                }
+            }
+            DebugOverlay::Rooms => {
+                let yellow = RGB::named(rltk::YELLOW);
+                for (i, room) in map.rooms.iter().enumerate() {
+                    // Floor runs from x1 + 1 to x2, so the walls ring it from x1 to x2 + 1
+                    ctx.draw_hollow_box(room.x1, room.y1, room.x2 + 1 - room.x1, room.y2 + 1 - room.y1, yellow, black);
+                    ctx.print_color(room.x1 + 1, room.y1, yellow, black, i.to_string());
+                }
+            }
+        }
+        ctx.print_color(1, 0, RGB::named(rltk::WHITE), black, format!(" {:?} ", self));
+    }
+}
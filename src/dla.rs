@@ -0,0 +1,61 @@
+use crate::{Map, TileType};
+use rltk::RandomNumberGenerator as RNG;
+
+/// Share of the map to dig out before stopping.
+const FLOOR_PERCENT: usize = 30;
+/// Size of the squares the cavern is split into when picking rooms.
+const SECTOR: i32 = 12;
+
+impl Map {
+    /// A cavern grown by diffusion limited aggregation: diggers wander in
+    /// from random spots and carve out the last wall they stood on before
+    /// bumping into the open area around the middle.
+    pub fn dla_map(seed: u64) -> Map {
+        let mut rng = RNG::seeded(seed);
+        let mut map = Map::filled(80, 50, TileType::Wall);
+        map.seed = seed;
+        let (cx, cy) = (map.width / 2, map.height / 2);
+        for (dx, dy) in [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)].iter() {
+            let idx = map.xy_idx(cx + dx, cy + dy);
+            map.tiles[idx] = TileType::Floor;
+        }
+
+        let target = map.tiles.len() * FLOOR_PERCENT / 100;
+        let mut floor = 5;
+        while floor < target {
+            let (mut x, mut y) = (rng.range(1, map.width - 1), rng.range(1, map.height - 1));
+            let (mut prev_x, mut prev_y) = (x, y);
+            while map.tiles[map.xy_idx(x, y)] == TileType::Wall {
+                prev_x = x;
+                prev_y = y;
+                match rng.range(0, 4) {
+                    0 if x > 1 => x -= 1,
+                    1 if x < map.width - 2 => x += 1,
+                    2 if y > 1 => y -= 1,
+                    3 if y < map.height - 2 => y += 1,
+                    _ => {}
+                }
+            }
+            let idx = map.xy_idx(prev_x, prev_y);
+            if map.tiles[idx] == TileType::Wall {
+                map.tiles[idx] = TileType::Floor;
+                floor += 1;
+            }
+        }
+
+        // One big cave, so carve it into squares to get a room for each
+        let sectors_x = (map.width + SECTOR - 1) / SECTOR;
+        let mut regions = vec![Vec::new(); (sectors_x * ((map.height + SECTOR - 1) / SECTOR)) as usize];
+        for idx in 0..map.tiles.len() {
+            if map.tiles[idx] == TileType::Floor {
+                let (x, y) = (idx as i32 % map.width, idx as i32 / map.width);
+                regions[((y / SECTOR) * sectors_x + x / SECTOR) as usize].push(idx);
+            }
+        }
+        // The room around the starting seed goes first
+        let start = ((cy / SECTOR) * sectors_x + cx / SECTOR) as usize;
+        regions.swap(0, start);
+        map.rooms_from_regions(&regions, SECTOR as usize);
+        map
+    }
+}
@@ -0,0 +1,258 @@
+use rltk::{Algorithm2D, BaseMap, DistanceAlg, LineAlg, Point};
+use std::collections::HashSet;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum FovAlgorithm {
+    /// rltk's built-in `field_of_view`
+    Rltk,
+    SymmetricShadowcasting,
+    Permissive,
+    Raycasting,
+}
+
+pub fn field_of_view<M: BaseMap + Algorithm2D>(
+    algorithm: FovAlgorithm,
+    origin: Point,
+    range: i32,
+    map: &M,
+) -> Vec<Point> {
+    let visible = match algorithm {
+        FovAlgorithm::Rltk => return rltk::field_of_view(origin, range, map),
+        FovAlgorithm::SymmetricShadowcasting => shadowcast(origin, range, map),
+        FovAlgorithm::Permissive => permissive(origin, range, map),
+        FovAlgorithm::Raycasting => raycast(origin, range, map),
+    };
+    visible.into_iter().collect()
+}
+
+fn in_range(origin: Point, target: Point, range: i32) -> bool {
+    DistanceAlg::Pythagoras.distance2d(origin, target) <= range as f32
+}
+
+fn is_opaque<M: BaseMap + Algorithm2D>(map: &M, point: Point) -> bool {
+    !map.in_bounds(point) || map.is_opaque(map.point2d_to_index(point))
+}
+
+/// Slopes are kept as exact fractions so that both ends of a line of sight
+/// agree on it, which is what makes the shadowcast symmetric.
+#[derive(Copy, Clone)]
+struct Slope {
+    num: i32,
+    den: i32,
+}
+
+struct Row {
+    depth: i32,
+    start: Slope,
+    end: Slope,
+}
+
+impl Row {
+    fn min_col(&self) -> i32 {
+        // round half up of depth * start
+        (2 * self.depth * self.start.num + self.start.den).div_euclid(2 * self.start.den)
+    }
+
+    fn max_col(&self) -> i32 {
+        // round half down of depth * end
+        -(-(2 * self.depth * self.end.num - self.end.den)).div_euclid(2 * self.end.den)
+    }
+
+    fn is_symmetric(&self, col: i32) -> bool {
+        col * self.start.den >= self.depth * self.start.num
+            && col * self.end.den <= self.depth * self.end.num
+    }
+}
+
+fn tile_slope(depth: i32, col: i32) -> Slope {
+    Slope { num: 2 * col - 1, den: 2 * depth }
+}
+
+/// Albert Ford's symmetric shadowcasting, one quadrant at a time.
+fn shadowcast<M: BaseMap + Algorithm2D>(origin: Point, range: i32, map: &M) -> HashSet<Point> {
+    let mut visible = HashSet::new();
+    visible.insert(origin);
+    let quadrants: [fn(Point, i32, i32) -> Point; 4] = [
+        |o, depth, col| Point::new(o.x + col, o.y - depth),
+        |o, depth, col| Point::new(o.x + col, o.y + depth),
+        |o, depth, col| Point::new(o.x + depth, o.y + col),
+        |o, depth, col| Point::new(o.x - depth, o.y + col),
+    ];
+
+    for transform in quadrants.iter() {
+        let mut rows = vec![Row {
+            depth: 1,
+            start: Slope { num: -1, den: 1 },
+            end: Slope { num: 1, den: 1 },
+        }];
+        while let Some(mut row) = rows.pop() {
+            if row.depth > range {
+                continue;
+            }
+            let mut prev_wall: Option<bool> = None;
+            for col in row.min_col()..=row.max_col() {
+                let point = transform(origin, row.depth, col);
+                let wall = is_opaque(map, point);
+                if (wall || row.is_symmetric(col)) && map.in_bounds(point) && in_range(origin, point, range) {
+                    visible.insert(point);
+                }
+                if prev_wall == Some(true) && !wall {
+                    row.start = tile_slope(row.depth, col);
+                }
+                if prev_wall == Some(false) && wall {
+                    rows.push(Row { depth: row.depth + 1, start: row.start, end: tile_slope(row.depth, col) });
+                }
+                prev_wall = Some(wall);
+            }
+            if prev_wall == Some(false) {
+                rows.push(Row { depth: row.depth + 1, start: row.start, end: row.end });
+            }
+        }
+    }
+    visible
+}
+
+/// Sub-tile resolution used by the permissive line checks.
+const SUBCELL: i32 = 8;
+const SAMPLE_OFFSETS: [i32; 3] = [1, 4, 7];
+
+/// A tile is visible if any line between sample points of the two tiles
+/// clears every opaque tile in between. Lines are walked in integer
+/// sub-tile steps from both ends alike, so the result is symmetric.
+fn permissive<M: BaseMap + Algorithm2D>(origin: Point, range: i32, map: &M) -> HashSet<Point> {
+    let mut visible = HashSet::new();
+    for y in origin.y - range..=origin.y + range {
+        for x in origin.x - range..=origin.x + range {
+            let target = Point::new(x, y);
+            if map.in_bounds(target) && in_range(origin, target, range) && permissive_line(origin, target, map) {
+                visible.insert(target);
+            }
+        }
+    }
+    visible
+}
+
+fn permissive_line<M: BaseMap + Algorithm2D>(from: Point, to: Point, map: &M) -> bool {
+    if from == to {
+        return true;
+    }
+    for ax in SAMPLE_OFFSETS.iter() {
+        for ay in SAMPLE_OFFSETS.iter() {
+            for bx in SAMPLE_OFFSETS.iter() {
+                for by in SAMPLE_OFFSETS.iter() {
+                    let a = (from.x * SUBCELL + ax, from.y * SUBCELL + ay);
+                    let b = (to.x * SUBCELL + bx, to.y * SUBCELL + by);
+                    if segment_is_clear(a, b, from, to, map) {
+                        return true;
+                    }
+                }
+            }
+        }
+    }
+    false
+}
+
+fn segment_is_clear<M: BaseMap + Algorithm2D>(
+    a: (i32, i32),
+    b: (i32, i32),
+    from: Point,
+    to: Point,
+    map: &M,
+) -> bool {
+    let steps = i32::max((b.0 - a.0).abs(), (b.1 - a.1).abs());
+    for k in 1..steps {
+        let x = (a.0 * (steps - k) + b.0 * k).div_euclid(SUBCELL * steps);
+        let y = (a.1 * (steps - k) + b.1 * k).div_euclid(SUBCELL * steps);
+        let cell = Point::new(x, y);
+        if cell != from && cell != to && is_opaque(map, cell) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Casts a Bresenham ray at every tile on the edge of the view square and
+/// stops each ray at the first opaque tile.
+fn raycast<M: BaseMap + Algorithm2D>(origin: Point, range: i32, map: &M) -> HashSet<Point> {
+    let mut visible = HashSet::new();
+    visible.insert(origin);
+    let mut edge = Vec::new();
+    for i in -range..=range {
+        edge.push(Point::new(origin.x + i, origin.y - range));
+        edge.push(Point::new(origin.x + i, origin.y + range));
+        edge.push(Point::new(origin.x - range, origin.y + i));
+        edge.push(Point::new(origin.x + range, origin.y + i));
+    }
+    for end in edge {
+        for point in rltk::line2d(LineAlg::Bresenham, origin, end).into_iter().skip(1) {
+            if !map.in_bounds(point) || !in_range(origin, point, range) {
+                break;
+            }
+            visible.insert(point);
+            if is_opaque(map, point) {
+                break;
+            }
+        }
+    }
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Map;
+    use rltk::RandomNumberGenerator as RNG;
+    use std::collections::HashMap;
+
+    /// Generates `maps` rooms-and-corridors maps and checks that for sampled
+    /// pairs of walkable tiles in range, A sees B exactly when B sees A.
+    fn assert_symmetric(algorithm: FovAlgorithm, maps: u64, range: i32) {
+        const ORIGINS_PER_MAP: usize = 5;
+
+        for seed in 0..maps {
+            let map = Map::new_map(seed);
+            let mut rng = RNG::seeded(seed);
+            let floor: Vec<Point> = (0..map.tiles.len())
+                .filter(|idx| map.tiles[*idx].is_walkable())
+                .map(|idx| Point::new(idx as i32 % map.width, idx as i32 / map.width))
+                .collect();
+            let mut cache: HashMap<Point, HashSet<Point>> = HashMap::new();
+            let mut fov_of = |p: Point| -> HashSet<Point> {
+                cache
+                    .entry(p)
+                    .or_insert_with(|| field_of_view(algorithm, p, range, &map).into_iter().collect())
+                    .clone()
+            };
+
+            for _ in 0..ORIGINS_PER_MAP {
+                let a = *rng.random_slice_entry(&floor).expect("map has no floor");
+                let seen_from_a = fov_of(a);
+                for b in floor.iter().filter(|b| in_range(a, **b, range)) {
+                    let a_sees_b = seen_from_a.contains(b);
+                    assert_eq!(
+                        a_sees_b,
+                        fov_of(*b).contains(&a),
+                        "{:?}: seed {} ({}, {}) {} ({}, {}) but not the other way around",
+                        algorithm,
+                        seed,
+                        a.x,
+                        a.y,
+                        if a_sees_b { "sees" } else { "is seen by" },
+                        b.x,
+                        b.y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shadowcasting_is_symmetric() {
+        assert_symmetric(FovAlgorithm::SymmetricShadowcasting, 20, 8);
+    }
+
+    #[test]
+    fn permissive_is_symmetric() {
+        assert_symmetric(FovAlgorithm::Permissive, 20, 8);
+    }
+}
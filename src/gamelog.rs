@@ -0,0 +1,9 @@
+pub struct GameLog {
+    pub entries: Vec<String>
+}
+
+impl GameLog {
+    pub fn log<S: ToString>(&mut self, entry: S) {
+        self.entries.push(entry.to_string());
+    }
+}
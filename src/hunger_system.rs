@@ -0,0 +1,44 @@
+use crate::{CombatStats, GameLog, HungerClock, HungerState, Player, SufferDamage};
+use specs::prelude::*;
+
+pub struct HungerSystem {}
+
+impl<'a> System<'a> for HungerSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, HungerClock>,
+        WriteStorage<'a, CombatStats>,
+        ReadStorage<'a, Player>,
+        WriteStorage<'a, SufferDamage>,
+        WriteExpect<'a, GameLog>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut clocks, mut combat_stats, players, mut inflict_damage, mut log) = data;
+
+        for (entity, clock, stats) in (&entities, &mut clocks, &mut combat_stats).join() {
+            let is_player = players.get(entity).is_some();
+            if clock.state == HungerState::Starving {
+                SufferDamage::new_damage(&mut inflict_damage, entity, 1, None);
+                if is_player {
+                    log.log("Your hunger pangs are getting painful! You suffer 1 hp damage.");
+                }
+                continue;
+            }
+            clock.turns -= 1;
+            if clock.turns > 0 {
+                continue;
+            }
+            let next = clock.state.next();
+            clock.set_state(next, stats);
+            if is_player {
+                match next {
+                    HungerState::Normal => log.log("You are no longer well fed."),
+                    HungerState::Hungry => log.log("You are hungry."),
+                    HungerState::Starving => log.log("You are starving!"),
+                    HungerState::WellFed => {}
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{component_names, CombatStats, Map, Name, Position, StatusEffect, SufferDamage, Viewshed, WantsToMelee};
+use rltk::{Algorithm2D, Rltk, RGB};
+use specs::prelude::*;
+
+/// Characters that fit inside the panel's border.
+const PANEL_WIDTH: usize = 40;
+
+/// The entity on `idx` to select after `current`, cycling through
+/// everything stacked on the tile.
+pub fn select_at(map: &Map, idx: usize, current: Option<Entity>) -> Option<Entity> {
+    let stack = map.entities_at(idx);
+    let next = current
+        .and_then(|e| stack.iter().position(|s| *s == e))
+        .map_or(0, |n| (n + 1) % stack.len());
+    stack.get(next).copied()
+}
+
+fn name_of(names: &ReadStorage<Name>, e: Entity) -> String {
+    names.get(e).map_or(format!("#{}", e.id()), |n| n.name.clone())
+}
+
+/// `entity`'s melee and damage intents. Those components are gone by the
+/// time the panel is drawn, so this is called before the systems that
+/// consume them run.
+pub fn intents(ecs: &World, entity: Entity) -> Vec<String> {
+    let names = ecs.read_storage::<Name>();
+    let mut lines = Vec::new();
+    if let Some(melee) = ecs.read_storage::<WantsToMelee>().get(entity) {
+        lines.push(format!("WantsToMelee {}", name_of(&names, melee.target)));
+    }
+    if let Some(damage) = ecs.read_storage::<SufferDamage>().get(entity) {
+        lines.push(format!("SufferDamage {:?}", damage.amount));
+        if let Some(attacker) = damage.last_attacker {
+            lines.push(format!("  last hit by {}", name_of(&names, attacker)));
+        }
+    }
+    lines
+}
+
+/// Tints the tiles `entity` can see and lists its components in a panel,
+/// along with the intents it had `this_turn`.
+pub fn draw_inspector(ecs: &World, entity: Entity, this_turn: &[String], ctx: &mut Rltk) {
+    let map = ecs.fetch::<Map>();
+    let names = ecs.read_storage::<Name>();
+    let positions = ecs.read_storage::<Position>();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let status_effects = ecs.read_storage::<StatusEffect>();
+    let name_of = |e: Entity| name_of(&names, e);
+
+    let mut lines = vec![format!("{} (#{})", name_of(entity), entity.id())];
+    if let Some(pos) = positions.get(entity) {
+        lines.push(format!("Position ({}, {})", pos.x, pos.y));
+    }
+    if let Some(viewshed) = viewsheds.get(entity) {
+        for tile in viewshed.visible_tiles.iter().filter(|t| map.in_bounds(**t)) {
+            ctx.set_bg(tile.x, tile.y, RGB::from_f32(0.0, 0.2, 0.3));
+        }
+        lines.push(format!("Viewshed range {} {:?}", viewshed.range, viewshed.algorithm));
+        lines.push(format!("  {} tiles, dirty: {}", viewshed.visible_tiles.len(), viewshed.dirty));
+    }
+    if let Some(stats) = combat_stats.get(entity) {
+        lines.push(format!("HP {}/{} AC {} skill {}", stats.hp, stats.max_hp, stats.armor_class, stats.skill));
+        lines.push(format!("  damage {}d{}+{}", stats.damage.n_dice, stats.damage.die_type, stats.damage.bonus));
+    }
+    for effect in status_effects.join().filter(|e| e.target == entity) {
+        lines.push(format!("{:?} {} turns", effect.kind, effect.turns));
+    }
+    if !this_turn.is_empty() {
+        lines.push("This turn:".to_string());
+        lines.extend(this_turn.iter().map(|line| format!("  {}", line)));
+    }
+    // Melee queues damage after DamageSystem has run, so it shows up here
+    // a turn before it lands
+    lines.extend(intents(ecs, entity).into_iter().map(|line| format!("Queued {}", line)));
+    let described = ["Name", "Position", "Viewshed", "CombatStats", "WantsToMelee", "SufferDamage"];
+    let mut line = String::new();
+    for name in component_names(ecs, entity).into_iter().filter(|c| !described.contains(c)) {
+        if !line.is_empty() && line.len() + 1 + name.len() > PANEL_WIDTH {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(name);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    let white = RGB::named(rltk::WHITE);
+    let black = RGB::named(rltk::BLACK);
+    ctx.draw_box(38, 1, PANEL_WIDTH as i32 + 1, lines.len() as i32 + 1, white, black);
+    ctx.print_color(40, 1, RGB::named(rltk::YELLOW), black, " Inspector ");
+    for (y, line) in (2..).zip(lines.iter()) {
+        ctx.print(39, y, line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{register_components, Enemy};
+
+    #[test]
+    fn intents_and_every_component_are_listed() {
+        let mut ecs = World::new();
+        register_components(&mut ecs);
+        let goblin = ecs.create_entity().with(Name { name: "Goblin".to_string() }).build();
+        let rat = ecs
+            .create_entity()
+            .with(Name { name: "Rat".to_string() })
+            .with(Enemy {})
+            .with(WantsToMelee { target: goblin })
+            .build();
+        assert_eq!(intents(&ecs, rat), vec!["WantsToMelee Goblin".to_string()]);
+        assert_eq!(component_names(&ecs, rat), vec!["Enemy", "Name", "WantsToMelee"]);
+    }
+}
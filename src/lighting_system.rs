@@ -0,0 +1,37 @@
+use crate::{LightSource, Map, Position};
+use rltk::{field_of_view, DistanceAlg, Point, RGB};
+use specs::prelude::*;
+
+/// Rebuilds `Map::light` from every light source, fading linearly to
+/// nothing at the edge of each source's radius.
+pub struct LightingSystem {}
+
+impl<'a> System<'a> for LightingSystem {
+    type SystemData = (
+        WriteExpect<'a, Map>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, LightSource>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut map, positions, lights) = data;
+
+        for light in map.light.iter_mut() {
+            *light = RGB::from_f32(0., 0., 0.);
+        }
+        for (pos, source) in (&positions, &lights).join() {
+            let origin = Point::new(pos.x, pos.y);
+            let lit = field_of_view(origin, source.radius, &*map);
+            for tile in lit {
+                if tile.x < 0 || tile.x >= map.width || tile.y < 0 || tile.y >= map.height {
+                    continue;
+                }
+                let idx = map.xy_idx(tile.x, tile.y);
+                let distance = DistanceAlg::Pythagoras.distance2d(origin, tile);
+                let intensity = (1.0 - distance / source.radius as f32).max(0.0);
+                let mixed = map.light[idx] + source.color * intensity;
+                map.light[idx] = RGB::from_f32(mixed.r.min(1.0), mixed.g.min(1.0), mixed.b.min(1.0));
+            }
+        }
+    }
+}
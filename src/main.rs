@@ -1,5 +1,5 @@
 use rltk::RandomNumberGenerator as RNG;
-use rltk::{GameState, Rltk, VirtualKeyCode, RGB, Point};
+use rltk::{Algorithm2D, DiceType, GameState, Rltk, VirtualKeyCode, RGB, Point};
 use specs::prelude::*;
 // use specs_derive::Component;
 mod rect;
@@ -8,7 +8,7 @@ mod player;
 use player::*;
 mod map;
 pub use map::*;
-use std::{thread, time};
+use std::time::Instant;
 mod components;
 pub use components::*;
 mod visibility_system;
@@ -19,204 +19,602 @@ mod map_indexing_system;
 use map_indexing_system::*;
 mod combat_system;
 use combat_system::*;
+mod status_effect_system;
+mod corpse_system;
+mod trigger_system;
+mod terrain_system;
+mod lighting_system;
+mod fov;
+mod memory_system;
+mod hunger_system;
+mod regeneration_system;
+use regeneration_system::RegenerationSystem;
+use hunger_system::HungerSystem;
+use memory_system::MemorySystem;
+use fov::FovAlgorithm;
+use lighting_system::LightingSystem;
+use terrain_system::TerrainDamageSystem;
+use trigger_system::TriggerSystem;
+use corpse_system::CorpseDecaySystem;
+use status_effect_system::StatusEffectSystem;
 mod ui;
+mod debug_console;
+mod inspector;
+mod debug_overlay;
+mod profiler;
+use profiler::Profiler;
+use debug_overlay::DebugOverlay;
+use debug_console::{Command, DebugConsole};
+mod spawner;
+mod prefab;
+mod map_ascii;
+mod room_builder;
+mod wfc;
+mod voronoi;
+mod dla;
+mod gamelog;
+pub use gamelog::GameLog;
+
+/// Something the player keeps doing over several turns until it finishes,
+/// gets interrupted, or a key is pressed.
+#[derive(PartialEq, Clone)]
+pub enum AutoAction {
+    Rest,
+    /// Remembers the hp and visible items from the last step so that
+    /// taking damage or spotting something new stops the walk.
+    Explore { hp: i32, items: Vec<Entity> },
+    /// Walks to a clicked tile, stopping if an enemy not already in view
+    /// shows up.
+    Travel { target: usize, hp: i32, hostiles: Vec<Entity> },
+    Run { dx: i32, dy: i32, corridor: bool, hp: i32 },
+}
 
 #[derive(PartialEq, Copy, Clone)]
 pub enum RunState {
     Paused,
-    Running
+    AwaitingInput,
+    PlayerTurn,
+    LevelUp
 }
 
 pub struct State {
     pub ecs: World,
     pub rng: RNG,
-    pub runstate: RunState
+    pub runstate: RunState,
+    pub mapindex: MapIndexingSystem,
+    pub auto: Option<AutoAction>,
+    /// Only present in debug builds or with `--debug`
+    pub console: Option<DebugConsole>,
+    /// While set, clicks select entities for the inspector instead of travelling
+    pub inspecting: bool,
+    pub inspected: Option<Entity>,
+    /// What the inspected entity meant to do this turn, saved before the
+    /// systems acting on it remove the components
+    pub inspected_intents: Vec<String>,
+    pub overlay: DebugOverlay,
+    pub profiler: Profiler,
+    pub show_profiler: bool,
+    pub last_frame: Instant
 }
+
+/// Frames per second rltk holds the game to, about the 60ms a frame it
+/// always took.
+const FPS_CAP: f32 = 16.0;
 impl GameState for State {
     fn tick(&mut self, ctx: &mut Rltk) {
-        if self.runstate == RunState::Running {
-            ctx.cls();
-            ui::draw_ui(&self.ecs, ctx);
+        ctx.cls();
+        if self.runstate == RunState::PlayerTurn {
             self.run_systems();
             DamageSystem::delete_the_dead(&mut self.ecs);
-            self.handle_input(ctx);
+            self.runstate = if player_has_stat_points(&self.ecs) {
+                RunState::LevelUp
+            } else {
+                RunState::AwaitingInput
+            };
+        }
+        self.handle_input(ctx);
 
-            let map = self.ecs.fetch::<Map>();
-            map.draw_map(ctx);
+        let map = self.ecs.fetch::<Map>();
+        map.draw_map(ctx);
 
-            let positions = self.ecs.read_storage::<Position>();
-            let renderables = self.ecs.read_storage::<Renderable>();
+        let positions = self.ecs.read_storage::<Position>();
+        let renderables = self.ecs.read_storage::<Renderable>();
+        let hidden = self.ecs.read_storage::<Hidden>();
 
-            for (pos, render) in (&positions, &renderables).join() {
-                let idx = map.xy_idx(pos.x, pos.y);
-                if map.visible_tiles[idx] {
-                ctx.set(pos.x, pos.y, render.fg, render.bg, render.glyph);
-                }
+        let mut drawn = (&positions, &renderables, !&hidden).join()
+            .map(|(pos, render, _)| (pos, render))
+            .collect::<Vec<_>>();
+        drawn.sort_by_key(|(_, render)| std::cmp::Reverse(render.render_order));
+        for (pos, render) in drawn {
+            let idx = map.xy_idx(pos.x, pos.y);
+            if map.visible_tiles[idx] {
+            ctx.set(pos.x, pos.y, render.fg, render.bg, render.glyph);
             }
-            // TODO: something more robust, account for frametime
-            thread::sleep(time::Duration::from_millis(60))
-        } else {
-            self.handle_input(ctx)
         }
+        self.overlay.draw(&self.ecs, ctx);
+        if let Some(entity) = self.inspected.filter(|e| self.inspecting && self.ecs.is_alive(*e)) {
+            inspector::draw_inspector(&self.ecs, entity, &self.inspected_intents, ctx);
+        }
+        ui::draw_ui(&self.ecs, ctx);
+        if self.runstate == RunState::LevelUp {
+            ui::draw_level_up(&self.ecs, ctx);
+        }
+        if self.runstate == RunState::Paused {
+            ctx.print(1, 1, "Paused");
+        }
+        if let Some(console) = self.console.as_ref().filter(|c| c.open) {
+            console.draw(ctx);
+        }
+        if self.show_profiler {
+            self.profiler.draw(ctx);
+        }
+
+        self.profiler.record("Frame", self.last_frame.elapsed());
+        self.last_frame = Instant::now();
     }
 }
 impl State {
     fn handle_input(&mut self, ctx: &mut Rltk) {
+        if let Some(console) = self.console.as_mut() {
+            if ctx.key == Some(VirtualKeyCode::Grave) {
+                console.open = !console.open;
+                return
+            }
+            if console.open {
+                if let Some(line) = ctx.key.and_then(|key| console.handle_key(key, ctx.shift)) {
+                    self.run_command(&line);
+                }
+                return
+            }
+        }
+        if ctx.key == Some(VirtualKeyCode::N) {
+            self.overlay = self.overlay.next();
+            return
+        }
+        if ctx.key == Some(VirtualKeyCode::F3) {
+            self.show_profiler = !self.show_profiler;
+            return
+        }
         if self.runstate == RunState::Paused {
             match ctx.key {
                 None => {}
                 Some(key) => match key {
                     VirtualKeyCode::R => self.regen_map(false),
                     VirtualKeyCode::F => self.reveal_all(),
-                    VirtualKeyCode::P => self.toggle_runstate(ctx),
-                    VirtualKeyCode::N => self.draw_pathing(ctx),
+                    VirtualKeyCode::P => self.toggle_runstate(),
                     _ => {}
                 }
             }
             return
         }
+        if self.runstate == RunState::LevelUp {
+            let choice = match ctx.key {
+                Some(VirtualKeyCode::A) => Some(LevelUpChoice::ArmorClass),
+                Some(VirtualKeyCode::B) => Some(LevelUpChoice::Skill),
+                Some(VirtualKeyCode::C) => Some(LevelUpChoice::MaxHp),
+                _ => None
+            };
+            if let Some(choice) = choice {
+                spend_stat_point(&mut self.ecs, choice);
+                if !player_has_stat_points(&self.ecs) {
+                    self.runstate = RunState::AwaitingInput;
+                }
+            }
+            return
+        }
+        if let Some(action) = self.auto.take() {
+            if ctx.key.is_some() || ctx.left_click {
+                self.ecs.write_resource::<GameLog>().log("You stop.");
+            } else {
+                self.auto = self.continue_auto(action);
+            }
+            return
+        }
+        if ctx.left_click && self.inspecting {
+            let map = self.ecs.fetch::<Map>();
+            let target = ctx.mouse_point();
+            if map.in_bounds(target) {
+                self.inspected = inspector::select_at(&map, map.xy_idx(target.x, target.y), self.inspected);
+                self.inspected_intents.clear();
+            }
+            return
+        }
+        if ctx.left_click {
+            self.start_travel(ctx.mouse_point());
+            return
+        }
         match ctx.key {
             None => {}
             Some(key) => match key {
-                VirtualKeyCode::Left => try_move_player(-1, 0, &mut self.ecs),
-                VirtualKeyCode::Right => try_move_player(1, 0, &mut self.ecs),
-                VirtualKeyCode::Up => try_move_player(0, -1, &mut self.ecs),
-                VirtualKeyCode::Down => try_move_player(0, 1, &mut self.ecs),
+                VirtualKeyCode::Left if ctx.shift => self.start_run(-1, 0),
+                VirtualKeyCode::Right if ctx.shift => self.start_run(1, 0),
+                VirtualKeyCode::Up if ctx.shift => self.start_run(0, -1),
+                VirtualKeyCode::Down if ctx.shift => self.start_run(0, 1),
+                VirtualKeyCode::Left => self.move_player(-1, 0),
+                VirtualKeyCode::Right => self.move_player(1, 0),
+                VirtualKeyCode::Up => self.move_player(0, -1),
+                VirtualKeyCode::Down => self.move_player(0, 1),
+                VirtualKeyCode::C => {
+                    close_doors(&mut self.ecs);
+                    self.runstate = RunState::PlayerTurn;
+                }
+                VirtualKeyCode::E => {
+                    eat_food(&mut self.ecs);
+                    self.runstate = RunState::PlayerTurn;
+                }
+                VirtualKeyCode::Q => {
+                    drink_potion(&mut self.ecs);
+                    self.runstate = RunState::PlayerTurn;
+                }
+                VirtualKeyCode::Space => self.runstate = RunState::PlayerTurn,
+                VirtualKeyCode::Z => self.start_rest(),
+                VirtualKeyCode::X => self.start_explore(),
+                VirtualKeyCode::I if self.console.is_some() => self.inspecting = !self.inspecting,
+                VirtualKeyCode::G => {
+                    pick_up_item(&mut self.ecs);
+                    self.runstate = RunState::PlayerTurn;
+                }
                 VirtualKeyCode::R => self.regen_map(false),
                 VirtualKeyCode::T => self.regen_map(true),
                 VirtualKeyCode::F => self.reveal_all(),
-                VirtualKeyCode::P => self.toggle_runstate(ctx),
+                VirtualKeyCode::P => self.toggle_runstate(),
                 _ => {}
             }
         }
     }
-    fn toggle_runstate(&mut self, ctx: &mut Rltk) {
-        match self.runstate {
-            RunState::Running => { self.runstate = RunState::Paused; ctx.print(1, 1, "Paused") },
-            RunState::Paused => { self.runstate = RunState::Running }
+    fn move_player(&mut self, delta_x: i32, delta_y: i32) {
+        try_move_player(delta_x, delta_y, &mut self.ecs);
+        self.runstate = RunState::PlayerTurn;
+    }
+    fn start_rest(&mut self) {
+        let message = match rest_interruption(&self.ecs) {
+            None => {
+                self.auto = Some(AutoAction::Rest);
+                "You rest.".to_string()
+            }
+            Some(RestInterruption::Hostile(name)) => format!("You can't rest with {} in view.", name),
+            Some(RestInterruption::Healed) => "You are already fully rested.".to_string(),
+            Some(RestInterruption::Starving) => "You are too hungry to rest.".to_string(),
+        };
+        self.ecs.write_resource::<GameLog>().log(message);
+    }
+    fn start_explore(&mut self) {
+        match hostile_in_view(&self.ecs) {
+            Some(hostile) => {
+                let message = format!("You can't explore with {} in view.", name_of(&self.ecs, hostile));
+                self.ecs.write_resource::<GameLog>().log(message);
+            }
+            None => {
+                self.auto = Some(AutoAction::Explore { hp: player_hp(&self.ecs), items: visible_items(&self.ecs) })
+            }
         }
     }
-    fn draw_pathing(&mut self, ctx: &mut Rltk) {
-        let map = self.ecs.fetch::<Map>();
-        let (mut y, mut x) = (0, 0);
-        for tile in map.blocked.iter() {
-            let glyph;
-                let fg;
-                if *tile {
-                        fg = RGB::from_f32(0.5, 0.0, 0.0);
-                        glyph = rltk::to_cp437('#');
-                    } else {
-                        fg = RGB::from_f32(0.0, 0.5, 0.0);
-                        glyph = rltk::to_cp437('#');
+    fn start_travel(&mut self, target: Point) {
+        let target = {
+            let map = self.ecs.fetch::<Map>();
+            if target.x < 0 || target.x >= map.width || target.y < 0 || target.y >= map.height {
+                return
+            }
+            let idx = map.xy_idx(target.x, target.y);
+            if !map.revealed_tiles[idx] {
+                return
+            }
+            idx
+        };
+        if travel_step(&self.ecs, target).is_none() {
+            self.ecs.write_resource::<GameLog>().log("You can't find a way there.");
+            return
+        }
+        self.auto = Some(AutoAction::Travel {
+            target,
+            hp: player_hp(&self.ecs),
+            hostiles: visible_hostiles(&self.ecs),
+        });
+    }
+    fn start_run(&mut self, delta_x: i32, delta_y: i32) {
+        let blocked = {
+            let map = self.ecs.fetch::<Map>();
+            let player_pos = self.ecs.fetch::<Point>();
+            map.is_blocked(map.xy_idx(player_pos.x + delta_x, player_pos.y + delta_y))
+        };
+        let hp = player_hp(&self.ecs);
+        self.move_player(delta_x, delta_y);
+        // Bumping into a monster or a door is just a single move
+        if !blocked {
+            self.auto = Some(AutoAction::Run { dx: delta_x, dy: delta_y, corridor: false, hp });
+        }
+    }
+    /// Why a multi-turn action should stop: an enemy that isn't in `known`
+    /// comes into view, or the player has lost hp since `hp` was taken.
+    fn new_threat(&self, hp: i32, known: &[Entity]) -> Option<String> {
+        if let Some(hostile) = visible_hostiles(&self.ecs).into_iter().find(|e| !known.contains(e)) {
+            Some(format!("{} comes into view", name_of(&self.ecs, hostile)))
+        } else if player_hp(&self.ecs) < hp {
+            Some("you are hurt".to_string())
+        } else {
+            None
+        }
+    }
+    /// Takes the next turn of `action`, returning it if it should carry on.
+    fn continue_auto(&mut self, action: AutoAction) -> Option<AutoAction> {
+        match action {
+            AutoAction::Rest => {
+                let stop = match rest_interruption(&self.ecs) {
+                    None => {
+                        self.runstate = RunState::PlayerTurn;
+                        return Some(action);
                     }
-                ctx.set(x, y, fg, RGB::from_f32(0., 0., 0.), glyph);
-
-            // Move the coordinates
-            x += 1;
-            if x > 79 {
-                x = 0;
-                y += 1;
+                    Some(RestInterruption::Hostile(name)) => format!("You stop resting: {} comes into view.", name),
+                    Some(RestInterruption::Healed) => "You feel fully rested.".to_string(),
+                    Some(RestInterruption::Starving) => "You stop resting: you are starving.".to_string(),
+                };
+                self.ecs.write_resource::<GameLog>().log(stop);
+                None
+            }
+            AutoAction::Explore { hp, items } => {
+                let current_hp = player_hp(&self.ecs);
+                let visible = visible_items(&self.ecs);
+                let new_item = visible.iter().find(|item| !items.contains(item));
+                let stop = if let Some(threat) = self.new_threat(hp, &[]) {
+                    format!("You stop exploring: {}.", threat)
+                } else if let Some(item) = new_item {
+                    format!("You stop exploring: you see a {}.", name_of(&self.ecs, *item))
+                } else if let Some((dx, dy)) = explore_step(&self.ecs) {
+                    self.move_player(dx, dy);
+                    return Some(AutoAction::Explore { hp: current_hp, items: visible });
+                } else {
+                    "There is nowhere left to explore.".to_string()
+                };
+                self.ecs.write_resource::<GameLog>().log(stop);
+                None
+            }
+            AutoAction::Travel { target, hp, hostiles } => {
+                if let Some(threat) = self.new_threat(hp, &hostiles) {
+                    self.ecs.write_resource::<GameLog>().log(format!("You stop travelling: {}.", threat));
+                    return None
+                }
+                let (dx, dy) = travel_step(&self.ecs, target)?;
+                self.move_player(dx, dy);
+                Some(AutoAction::Travel { target, hp: player_hp(&self.ecs), hostiles: visible_hostiles(&self.ecs) })
+            }
+            AutoAction::Run { dx, dy, corridor, hp } => {
+                if let Some(threat) = self.new_threat(hp, &[]) {
+                    self.ecs.write_resource::<GameLog>().log(format!("You stop running: {}.", threat));
+                    return None
+                }
+                let (dx, dy, corridor) = run_step(&self.ecs, dx, dy, corridor)?;
+                self.move_player(dx, dy);
+                Some(AutoAction::Run { dx, dy, corridor, hp: player_hp(&self.ecs) })
             }
         }
     }
+    fn toggle_runstate(&mut self) {
+        match self.runstate {
+            RunState::Paused => { self.runstate = RunState::AwaitingInput }
+            _ => { self.runstate = RunState::Paused }
+        }
+    }
     fn run_systems(&mut self) {
-        let mut lw = LeftWalker {};
-        lw.run_now(&self.ecs);
-        let mut vis = VisibilitySystem {};
-        vis.run_now(&self.ecs);
-        let mut mob = EnemyAI{};
-        mob.run_now(&self.ecs);
-        let mut mapindex = MapIndexingSystem{};
-        mapindex.run_now(&self.ecs);
-        let mut damage_system = DamageSystem{};
-        damage_system.run_now(&self.ecs);
-        let mut meele_system = MeleeCombatSystem{};
-        meele_system.run_now(&self.ecs);
+        let (ecs, profiler) = (&self.ecs, &mut self.profiler);
+        profiler.run(&mut LeftWalker {}, ecs);
+        profiler.run(&mut LightingSystem {}, ecs);
+        profiler.run(&mut VisibilitySystem {}, ecs);
+        profiler.run(&mut StatusEffectSystem {}, ecs);
+        profiler.run(&mut HungerSystem {}, ecs);
+        profiler.run(&mut RegenerationSystem {}, ecs);
+        profiler.run(&mut EnemyAI {}, ecs);
+        profiler.run(&mut self.mapindex, ecs);
+        profiler.run(&mut TriggerSystem {}, ecs);
+        profiler.run(&mut TerrainDamageSystem {}, ecs);
+        if let (true, Some(entity)) = (self.inspecting, self.inspected) {
+            self.inspected_intents = inspector::intents(ecs, entity);
+        }
+        profiler.run(&mut DamageSystem {}, ecs);
+        profiler.run(&mut MeleeCombatSystem {}, ecs);
+        profiler.run(&mut CorpseDecaySystem {}, ecs);
+        profiler.run(&mut MemorySystem {}, ecs);
+        let start = Instant::now();
         self.ecs.maintain();
+        self.profiler.record("maintain", start.elapsed());
     }
     fn reveal_all(&mut self) {
         let mut map = self.ecs.fetch_mut::<Map>();
-        map.revealed_tiles = vec![true; 80*50];
+        map.revealed_tiles = vec![true; map.tiles.len()];
     }
     fn regen_map(&mut self, test: bool) {
-        self.ecs.remove::<Map>();
         let map = if test {
             Map::test_map()
         } else {
-            Map::new_map(self.rng.range(0 as u64, 9999 as u64))
+            Map::new_map(self.rng.range(0_u64, 9999_u64))
         };
+        self.change_map(map);
+    }
+    /// Swaps in `map`, clears out everything left on the old one, puts the
+    /// player in its first room, fills the other rooms and spawns whatever
+    /// the generator asked for.
+    fn change_map(&mut self, map: Map) {
+        let start = Point::from_tuple(map.start_position());
+        let player_entity = *self.ecs.fetch::<Entity>();
+        // Everything else on the old map goes with it, along with whatever
+        // it was carrying
+        let mut left_behind: Vec<Entity> = (&self.ecs.entities(), &self.ecs.read_storage::<Position>())
+            .join()
+            .map(|(entity, _)| entity)
+            .filter(|entity| *entity != player_entity)
+            .collect();
+        {
+            let entities = self.ecs.entities();
+            let backpack = self.ecs.read_storage::<InBackpack>();
+            let equipped = self.ecs.read_storage::<Equipped>();
+            let carried: Vec<Entity> = entities
+                .join()
+                .filter(|item| {
+                    let owner = backpack.get(*item).map(|b| b.owner).or_else(|| equipped.get(*item).map(|e| e.owner));
+                    owner.is_some_and(|owner| left_behind.contains(&owner))
+                })
+                .collect();
+            left_behind.extend(carried);
+        }
+        self.ecs.delete_entities(&left_behind).expect("Unable to delete");
+        spawner::populate_rooms(&mut self.ecs, &mut self.rng, &map, 0);
         self.ecs.insert(map);
-        let map = self.ecs.fetch::<Map>();
-        let mut positions = self.ecs.write_storage::<Position>();
-        let mut players = self.ecs.write_storage::<Player>();
-        for (_player, pos) in (&mut players, &mut positions).join() {
-            let room0 = map.rooms[0].center();
-            pos.x = room0.0;
-            pos.y = room0.1;
+        if let Some(pos) = self.ecs.write_storage::<Position>().get_mut(player_entity) {
+            pos.x = start.x;
+            pos.y = start.y;
+        }
+        if let Some(viewshed) = self.ecs.write_storage::<Viewshed>().get_mut(player_entity) {
+            viewshed.dirty = true;
+        }
+        self.ecs.insert(start);
+        spawner::spawn_map_markers(&mut self.ecs);
+    }
+    /// Recomputes lighting and sight after the player was moved outside
+    /// of a turn.
+    fn refresh_view(&mut self) {
+        let mut lighting = LightingSystem {};
+        lighting.run_now(&self.ecs);
+        let mut vis = VisibilitySystem {};
+        vis.run_now(&self.ecs);
+    }
+    fn run_command(&mut self, line: &str) {
+        let result = Command::parse(line).and_then(|command| self.execute(command));
+        if let Some(console) = self.console.as_mut() {
+            match result {
+                Ok(message) => console.print(message),
+                Err(error) => console.print(format!("error: {}", error)),
+            }
+        }
+    }
+    fn execute(&mut self, command: Command) -> Result<String, String> {
+        let player_entity = *self.ecs.fetch::<Entity>();
+        let in_bounds = |map: &Map, x: i32, y: i32| {
+            if x < 0 || x >= map.width || y < 0 || y >= map.height {
+                Err(format!("({}, {}) is off the map", x, y))
+            } else {
+                Ok(map.xy_idx(x, y))
+            }
+        };
+        match command {
+            Command::Spawn { name, x, y } => {
+                {
+                    let map = self.ecs.fetch::<Map>();
+                    let idx = in_bounds(&map, x, y)?;
+                    if !map.tiles[idx].is_walkable() {
+                        return Err(format!("({}, {}) is not walkable", x, y));
+                    }
+                }
+                spawner::spawn_named(&mut self.ecs, &name, x, y).ok_or(format!("no monster or item called '{}'", name))?;
+                Ok(format!("spawned {} at ({}, {})", name, x, y))
+            }
+            Command::Give { name } => {
+                let template = spawner::item_template(&name).ok_or(format!("no item called '{}'", name))?;
+                let player_pos = *self.ecs.fetch::<Point>();
+                let item = spawner::spawn_item(&mut self.ecs, template, player_pos.x, player_pos.y);
+                take_item(&mut self.ecs, item);
+                Ok(format!("gave {}", template.name))
+            }
+            Command::Teleport { x, y } => {
+                {
+                    let mut map = self.ecs.fetch_mut::<Map>();
+                    let idx = in_bounds(&map, x, y)?;
+                    if !map.tiles[idx].is_walkable() || map.is_blocked(idx) {
+                        return Err(format!("({}, {}) is not free", x, y));
+                    }
+                    map.move_entity(player_entity, idx);
+                }
+                if let Some(pos) = self.ecs.write_storage::<Position>().get_mut(player_entity) {
+                    pos.x = x;
+                    pos.y = y;
+                }
+                if let Some(viewshed) = self.ecs.write_storage::<Viewshed>().get_mut(player_entity) {
+                    viewshed.dirty = true;
+                }
+                self.ecs.insert(Point::new(x, y));
+                self.refresh_view();
+                Ok(format!("teleported to ({}, {})", x, y))
+            }
+            Command::Heal => {
+                let mut combat_stats = self.ecs.write_storage::<CombatStats>();
+                let stats = combat_stats.get_mut(player_entity).ok_or("the player has no hp")?;
+                stats.hp = stats.max_hp;
+                Ok("healed".to_string())
+            }
+            Command::GodMode => {
+                let mut god_mode = self.ecs.write_storage::<GodMode>();
+                if god_mode.remove(player_entity).is_some() {
+                    Ok("god mode off".to_string())
+                } else {
+                    god_mode.insert(player_entity, GodMode {}).expect("Unable to insert god mode");
+                    Ok("god mode on".to_string())
+                }
+            }
+            Command::Reveal => {
+                self.reveal_all();
+                Ok("revealed the map".to_string())
+            }
+            Command::Regen { generator, seed } => {
+                let seed = seed.unwrap_or_else(|| self.rng.range(0_u64, 9999_u64));
+                let generate = match GENERATORS.iter().find(|(name, _)| *name == generator) {
+                    Some((_, generate)) => generate,
+                    None => {
+                        let names: Vec<&str> = GENERATORS.iter().map(|(name, _)| *name).collect();
+                        return Err(format!("unknown generator '{}', try {}", generator, names.join(", ")));
+                    }
+                };
+                let map = generate(seed);
+                self.change_map(map);
+                self.refresh_view();
+                Ok(format!("generated {} map with seed {}", generator, seed))
+            }
+            Command::Vault { name, x, y } => {
+                let prefab = prefab::vault(&name).ok_or(format!("no vault called '{}'", name))?;
+                {
+                    let mut map = self.ecs.fetch_mut::<Map>();
+                    if !prefab.stamp(&mut map, x, y) {
+                        return Err(format!("{} doesn't fit at ({}, {})", name, x, y));
+                    }
+                    map.populate_blocked();
+                }
+                spawner::spawn_map_markers(&mut self.ecs);
+                self.refresh_view();
+                Ok(format!("stamped {} at ({}, {})", name, x, y))
+            }
+            Command::Save { path } => {
+                let text = map_ascii::export(&self.ecs.fetch::<Map>());
+                std::fs::write(&path, text).map_err(|e| format!("{}: {}", path, e))?;
+                Ok(format!("saved the map to {}", path))
+            }
+            Command::Load { path } => {
+                let text = match map_ascii::FIXTURES.iter().find(|(name, _)| *name == path) {
+                    Some((_, text)) => text.to_string(),
+                    None => std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?,
+                };
+                let map = map_ascii::import(&text).map_err(|e| format!("{}: {}", path, e))?;
+                self.change_map(map);
+                self.refresh_view();
+                Ok(format!("loaded {}", path))
+            }
+            Command::Help => {
+                let names: Vec<&str> = GENERATORS.iter().map(|(name, _)| *name).collect();
+                Ok(format!("{}\ngenerators: {}", debug_console::HELP, names.join(", ")))
+            }
         }
     }
 }
 
-fn main() -> rltk::BError {
-    use rltk::RltkBuilder;
-    let context = RltkBuilder::simple80x50()
-        .with_title("Rusty Dungeon")
-        .build()?;
-    let mut gs = State { ecs: World::new(), rng: RNG::new(), runstate: RunState::Running};
-    /*TODO: somehow refactor this to form of
-    use components
-    ...
-    for component register <component>
-    */
+fn new_game(console: Option<DebugConsole>) -> State {
+    let mut gs = State { ecs: World::new(), rng: RNG::new(), runstate: RunState::PlayerTurn, mapindex: MapIndexingSystem::default(), auto: None, console, inspecting: false, inspected: None, inspected_intents: Vec::new(), overlay: DebugOverlay::Off, profiler: Profiler::default(), show_profiler: false, last_frame: Instant::now() };
     //let map = Map::new_map(0);
     let map = Map::test_map();
-    let player_spawn_room = gs.rng.range(0 as usize, map.rooms_n());
+    let player_spawn_room = gs.rng.range(0_usize, map.rooms_n());
     // let player_spawn_pos = map.rooms[player_spawn_room].center();
-    let player_spawn_pos = (10, 10);
-    gs.ecs.register::<Position>();
-    gs.ecs.register::<Renderable>();
-    gs.ecs.register::<LeftMover>();
-    gs.ecs.register::<Player>();
-    gs.ecs.register::<Viewshed>();
-    gs.ecs.register::<Enemy>();
-    gs.ecs.register::<Name>();
-    gs.ecs.register::<CombatStats>();
-    gs.ecs.register::<BlocksTile>();
-    gs.ecs.register::<SufferDamage>();
-    gs.ecs.register::<WantsToMelee>();
-    // enemy
-    for (idx, room) in map.rooms.iter().enumerate() {
-        if idx != player_spawn_room {
-        let (x,y) = room.center();
-        gs.ecs.create_entity()
-            .with(Position{ x, y })
-            .with(Renderable{
-                glyph: rltk::to_cp437('E'),
-                fg: RGB::named(rltk::RED),
-                bg: RGB::named(rltk::BLACK),
-            })
-            .with(Viewshed{ visible_tiles : Vec::new(), range: 8, dirty: true })
-            .with(Enemy{})
-            .with(BlocksTile{})
-            .with(CombatStats{ max_hp: 5, hp: 5, defense: 1, power: 1 })
-            .with(Name { name: format!("E#{}", idx)})
-            .build();
-        } else {}
-    }
+    let player_spawn_pos = spawner::spawn_point(&map, &mut gs.rng, &map.rooms[0], (10, 10)).unwrap_or_else(|| map.start_position());
+    register_components(&mut gs.ecs);
+    spawner::populate_rooms(&mut gs.ecs, &mut gs.rng, &map, player_spawn_room);
     // test enemy
-    gs.ecs.create_entity()
-            .with(Position{ x:5, y:5 })
-            .with(Renderable{
-                glyph: rltk::to_cp437('R'),
-                fg: RGB::named(rltk::RED),
-                bg: RGB::named(rltk::BLACK),
-            })
-            .with(Viewshed{ visible_tiles : Vec::new(), range: 8, dirty: true })
-            .with(Enemy{})
-            .with(BlocksTile{})
-            .with(CombatStats{ max_hp: 5, hp: 5, defense: 1, power: 1 })
-            .with(Name { name: format!("R#{}", 0)})
-            .build();
+    if let Some((x, y)) = spawner::spawn_point(&map, &mut gs.rng, &Rect::new(0, 0, 10, 10), (5, 5)) {
+        spawner::spawn_monster(&mut gs.ecs, spawner::monster_template("Rat").unwrap(), x, y);
+    }
     // player
     let player_entity = gs.ecs
         .create_entity()
@@ -225,16 +623,121 @@ fn main() -> rltk::BError {
             glyph: rltk::to_cp437('@'),
             fg: RGB::named(rltk::YELLOW),
             bg: RGB::named(rltk::BLACK),
+            render_order: 0,
         })
-        .with(Viewshed::new(8))
+        .with(Viewshed::with_algorithm(8, FovAlgorithm::SymmetricShadowcasting))
         .with(Player {})
         .with(BlocksTile{})
-        .with(CombatStats{ max_hp: 20, hp: 20, defense: 1, power: 3 })
+        .with(CombatStats{ max_hp: 20, hp: 20, armor_class: 12, skill: 3, damage: DiceType::new(1, 6, 1) })
         .with(Name { name: "Player".to_string()})
+        .with(Experience { level: 1, xp: 0, stat_points: 0 })
+        .with(LightSource { radius: 8, color: RGB::from_f32(1.0, 0.9, 0.7) })
+        .with(HungerClock { state: HungerState::WellFed, turns: HungerState::WellFed.duration() })
+        .with(NaturalRegeneration { counter: 0 })
         .build();
     gs.ecs.insert(player_entity);
     gs.ecs.insert(Point::from_tuple(player_spawn_pos));
     gs.ecs.insert(map);
+    gs.ecs.insert(RNG::seeded(gs.rng.next_u64()));
+    gs.ecs.insert(GameLog { entries: vec!["Welcome to Rusty Dungeon".to_string()] });
+    spawner::spawn_map_markers(&mut gs.ecs);
     // create mor entities here
+    gs
+}
+
+/// `--profile [turns]` plays the game headless, auto-exploring with god mode
+/// on, and prints how long each system took.
+fn profile(turns: u64) -> rltk::BError {
+    let mut gs = new_game(None);
+    let player_entity = *gs.ecs.fetch::<Entity>();
+    gs.ecs.write_storage::<GodMode>().insert(player_entity, GodMode {})?;
+    for _ in 0..turns {
+        gs.run_systems();
+        DamageSystem::delete_the_dead(&mut gs.ecs);
+        while player_has_stat_points(&gs.ecs) {
+            spend_stat_point(&mut gs.ecs, LevelUpChoice::MaxHp);
+        }
+        if let Some((dx, dy)) = explore_step(&gs.ecs) {
+            try_move_player(dx, dy, &mut gs.ecs);
+        }
+    }
+    println!("{} turns", turns);
+    print!("{}", gs.profiler.report());
+    Ok(())
+}
+
+fn main() -> rltk::BError {
+    use rltk::RltkBuilder;
+    let args: Vec<String> = std::env::args().collect();
+    let arg = |n: usize, default: u64| args.get(n).and_then(|a| a.parse().ok()).unwrap_or(default);
+    match args.get(1).map(String::as_str) {
+        Some("--profile") => return profile(arg(2, 1000)),
+        Some("--export-map") => {
+            let generate = args.get(2).and_then(|g| GENERATORS.iter().find(|(name, _)| name == g));
+            let (_, generate) = generate.ok_or("--export-map <generator> [seed]")?;
+            print!("{}", map_ascii::export(&generate(arg(3, 0))));
+            return Ok(());
+        }
+        _ => {}
+    }
+    let console = if cfg!(debug_assertions) || args.iter().any(|a| a == "--debug") {
+        Some(DebugConsole::default())
+    } else {
+        None
+    };
+    let context = RltkBuilder::simple80x50()
+        .with_title("Rusty Dungeon")
+        .with_fps_cap(FPS_CAP)
+        .build()?;
+    let gs = new_game(console);
     rltk::main_loop(context, gs)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regen_replaces_everything_on_the_old_map() {
+        let mut gs = new_game(None);
+        gs.run_systems();
+        let old: Vec<Entity> = (&gs.ecs.entities(), &gs.ecs.read_storage::<Enemy>()).join().map(|(e, _)| e).collect();
+        assert!(!old.is_empty());
+        let sword = spawner::spawn_item(&mut gs.ecs, spawner::item_template("Sword").unwrap(), 0, 0);
+        gs.ecs.write_storage::<Position>().remove(sword);
+        gs.ecs.write_storage::<Equipped>().insert(sword, Equipped { owner: old[0] }).unwrap();
+
+        // Prefab levels are smaller than the test map, so anything left over
+        // would be indexed off the end of it
+        let map = Map::prefab_level(3);
+        assert!(map.tiles.len() < 80 * 50);
+        gs.change_map(map);
+        gs.run_systems();
+        assert!(old.iter().all(|e| !gs.ecs.is_alive(*e)));
+        assert!(!gs.ecs.is_alive(sword));
+        let map = gs.ecs.fetch::<Map>();
+        for pos in gs.ecs.read_storage::<Position>().join() {
+            assert!(map.in_bounds(Point::new(pos.x, pos.y)));
+        }
+
+        // The new map's rooms are filled like a new game's
+        let player = *gs.ecs.fetch::<Entity>();
+        let entities = gs.ecs.entities();
+        let monsters = gs.ecs.read_storage::<Enemy>().join().count();
+        let braziers = (&entities, &gs.ecs.read_storage::<LightSource>()).join().filter(|(e, _)| *e != player).count();
+        let traps = gs.ecs.read_storage::<EntryTrigger>().join().count();
+        assert!(monsters > 0 && braziers > 0 && traps > 0, "{} monsters, {} braziers, {} traps", monsters, braziers, traps);
+    }
+
+    #[test]
+    fn loading_a_fixture_replaces_the_map() {
+        let mut gs = new_game(None);
+        gs.run_systems();
+        assert!(gs.execute(Command::Load { path: "crossroads".to_string() }).is_ok());
+        gs.run_systems();
+        let map = gs.ecs.fetch::<Map>();
+        assert_eq!((map.width, map.height), (31, 15));
+        let player_pos = *gs.ecs.fetch::<Point>();
+        assert!(map.tiles[map.xy_idx(player_pos.x, player_pos.y)].is_walkable());
+    }
+}
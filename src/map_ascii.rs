@@ -0,0 +1,194 @@
+use crate::{Map, Rect, TileType};
+
+/// Maps kept in the repo for trying things out and for tests.
+pub const FIXTURES: &[(&str, &str)] = &[
+    ("crossroads", include_str!("../maps/crossroads.txt")),
+    ("rooms_1234", include_str!("../maps/rooms_1234.txt")),
+];
+
+fn tile_char(tile: TileType) -> char {
+    match tile {
+        TileType::Wall => '#',
+        TileType::Floor => '.',
+        TileType::DoorClosed => '+',
+        TileType::DoorOpen => '/',
+        TileType::Water => '~',
+        TileType::Lava => '=',
+        TileType::Chasm => ':',
+        TileType::Rubble => ',',
+    }
+}
+
+fn char_tile(c: char) -> Option<TileType> {
+    match c {
+        '#' => Some(TileType::Wall),
+        '.' => Some(TileType::Floor),
+        '+' => Some(TileType::DoorClosed),
+        '/' => Some(TileType::DoorOpen),
+        '~' => Some(TileType::Water),
+        '=' => Some(TileType::Lava),
+        ':' => Some(TileType::Chasm),
+        ',' => Some(TileType::Rubble),
+        _ => None,
+    }
+}
+
+/// Writes the map as a header of `key value` lines, a `---` separator and
+/// then one character per tile.
+pub fn export(map: &Map) -> String {
+    let mut text = format!("width {}\nheight {}\nseed {}\n", map.width, map.height, map.seed);
+    for room in map.rooms.iter() {
+        text += &format!("room {} {} {} {}\n", room.x1, room.y1, room.x2, room.y2);
+    }
+    text += "---\n";
+    for row in map.tiles.chunks(map.width as usize) {
+        text.extend(row.iter().map(|tile| tile_char(*tile)));
+        text.push('\n');
+    }
+    text
+}
+
+pub fn import(text: &str) -> Result<Map, String> {
+    let mut lines = text.lines().enumerate();
+    let (mut width, mut height, mut seed, mut rooms) = (None, None, 0, Vec::new());
+    for (n, line) in lines.by_ref() {
+        if line == "---" {
+            break;
+        }
+        let error = |what: &str| format!("line {}: {}", n + 1, what);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let numbers = || {
+            words[1..]
+                .iter()
+                .map(|w| w.parse::<i32>().map_err(|_| error(&format!("'{}' is not a number", w))))
+                .collect::<Result<Vec<i32>, String>>()
+        };
+        match words.first() {
+            None => {}
+            Some(&"seed") => seed = words.get(1).and_then(|w| w.parse().ok()).ok_or(error("bad seed"))?,
+            Some(key) => match (*key, numbers()?.as_slice()) {
+                ("width", [w]) => width = Some(*w),
+                ("height", [h]) => height = Some(*h),
+                ("room", [x1, y1, x2, y2]) => rooms.push(Rect { x1: *x1, y1: *y1, x2: *x2, y2: *y2 }),
+                _ => return Err(error(&format!("can't read '{}' header", key))),
+            },
+        }
+    }
+    let width = width.ok_or("missing width")?;
+    let height = height.ok_or("missing height")?;
+    if width < 1 || height < 1 {
+        return Err(format!("map can't be {}x{}", width, height));
+    }
+
+    let mut map = Map::filled(width, height, TileType::Wall);
+    map.seed = seed;
+    let rows: Vec<(usize, &str)> = lines.collect();
+    if rows.len() != height as usize {
+        return Err(format!("expected {} rows of tiles, found {}", height, rows.len()));
+    }
+    for (y, (n, row)) in rows.iter().enumerate() {
+        if row.chars().count() != width as usize {
+            return Err(format!("line {}: expected {} tiles", n + 1, width));
+        }
+        for (x, c) in row.chars().enumerate() {
+            let tile = char_tile(c).ok_or(format!("line {}: unknown tile '{}'", n + 1, c))?;
+            let idx = map.xy_idx(x as i32, y as i32);
+            map.tiles[idx] = tile;
+        }
+    }
+    for room in rooms.iter() {
+        if room.x1 < 0 || room.y1 < 0 || room.x2 >= width || room.y2 >= height {
+            return Err(format!("room ({}, {}) to ({}, {}) is off the map", room.x1, room.y1, room.x2, room.y2));
+        }
+    }
+    if rooms.is_empty() {
+        rooms.push(Rect::new(0, 0, width - 2, height - 2));
+    }
+    map.rooms = rooms;
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_system::EnemyAI;
+    use crate::map_indexing_system::MapIndexingSystem;
+    use crate::visibility_system::VisibilitySystem;
+    use crate::{register_components, spawner, BlocksTile, GameLog, Player, Position, Viewshed, WantsToMelee};
+    use rltk::{Point, RandomNumberGenerator as RNG, RGB};
+    use specs::prelude::*;
+
+    fn fixture(name: &str) -> Map {
+        let (_, text) = FIXTURES.iter().find(|(n, _)| *n == name).unwrap();
+        import(text).unwrap()
+    }
+
+    /// A world on `map` with every tile lit, so monsters can see.
+    fn world_on(mut map: Map) -> World {
+        let mut ecs = World::new();
+        register_components(&mut ecs);
+        map.light = vec![RGB::from_f32(1.0, 1.0, 1.0); map.tiles.len()];
+        ecs.insert(map);
+        ecs.insert(RNG::seeded(1));
+        ecs.insert(GameLog { entries: Vec::new() });
+        ecs
+    }
+
+    #[test]
+    fn every_generator_and_fixture_round_trips() {
+        for (name, generate) in crate::map::GENERATORS.iter() {
+            for seed in 0..5 {
+                let text = export(&generate(seed));
+                assert_eq!(export(&import(&text).unwrap()), text, "{} map with seed {} changed on reimport", name, seed);
+            }
+        }
+        for (name, text) in FIXTURES.iter() {
+            let map = import(text).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert_eq!(map.regions().len(), 1, "fixture {} isn't connected", name);
+            assert_eq!(export(&map), *text, "fixture {} changed on reimport", name);
+        }
+    }
+
+    #[test]
+    fn closed_doors_block_sight_on_crossroads() {
+        let mut ecs = world_on(fixture("crossroads"));
+        let looker = ecs.create_entity().with(Position { x: 5, y: 2 }).with(Viewshed::new(8)).build();
+        VisibilitySystem {}.run_now(&ecs);
+
+        let viewsheds = ecs.read_storage::<Viewshed>();
+        let visible = &viewsheds.get(looker).unwrap().visible_tiles;
+        // The whole first room and both of its doors, but nothing past them
+        for (x, y) in [(1, 1), (9, 1), (1, 4), (9, 4), (10, 3), (5, 5)].iter() {
+            assert!(visible.contains(&Point::new(*x, *y)), "({}, {}) should be visible", x, y);
+        }
+        for (x, y) in [(11, 3), (5, 6)].iter() {
+            assert!(!visible.contains(&Point::new(*x, *y)), "({}, {}) should be hidden", x, y);
+        }
+    }
+
+    #[test]
+    fn monsters_path_to_and_attack_the_player_on_crossroads() {
+        let mut ecs = world_on(fixture("crossroads"));
+        let player = ecs.create_entity().with(Position { x: 12, y: 3 }).with(Player {}).with(BlocksTile {}).build();
+        ecs.insert(player);
+        ecs.insert(Point::new(12, 3));
+        let goblin = spawner::spawn_monster(&mut ecs, spawner::monster_template("Goblin").unwrap(), 18, 3);
+
+        let mut indexer = MapIndexingSystem::default();
+        let mut attacked = false;
+        for _ in 0..10 {
+            VisibilitySystem {}.run_now(&ecs);
+            EnemyAI {}.run_now(&ecs);
+            indexer.run_now(&ecs);
+            ecs.maintain();
+            if let Some(melee) = ecs.write_storage::<WantsToMelee>().remove(goblin) {
+                assert_eq!(melee.target, player);
+                attacked = true;
+                break;
+            }
+        }
+        assert!(attacked, "the goblin never attacked");
+        let goblin_pos = ecs.read_storage::<Position>().get(goblin).map(|p| (p.x, p.y));
+        assert_eq!(goblin_pos, Some((13, 3)));
+    }
+}
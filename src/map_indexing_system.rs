@@ -1,28 +1,170 @@
-use crate::{BlocksTile, Map, Position};
+use crate::{BlocksTile, EntryTrigger, Hidden, Map, Position};
 use specs::prelude::*;
 
-pub struct MapIndexingSystem {}
+/// Keeps the map's spatial index in step with `Position` change events.
+/// A map that has never been indexed is rebuilt from scratch.
+#[derive(Default)]
+pub struct MapIndexingSystem {
+    reader: Option<ReaderId<ComponentEvent>>,
+}
 
 impl<'a> System<'a> for MapIndexingSystem {
     type SystemData = (
         WriteExpect<'a, Map>,
-        ReadStorage<'a, Position>,
+        WriteStorage<'a, Position>,
         ReadStorage<'a, BlocksTile>,
-        Entities<'a>
+        Entities<'a>,
+        ReadStorage<'a, EntryTrigger>,
+        ReadStorage<'a, Hidden>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, position, blockers, entities) = data;
+        let (mut map, mut position, blockers, entities, triggers, hidden) = data;
 
-        map.populate_blocked();
-        map.clear_content_index();
-        for (position, entity) in (&position, &entities).join() {
+        let reader = self.reader.get_or_insert_with(|| position.register_reader());
+        if !map.indexed {
+            position.channel().read(reader).for_each(drop);
+            map.clear_content_index();
+            for (position, entity) in (&position, &entities).join() {
+                let idx = map.xy_idx(position.x, position.y);
+                map.index_entity(entity, idx, blockers.contains(entity));
+            }
+            map.indexed = true;
+        } else {
+            for event in position.channel().read(reader) {
+                match event {
+                    ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) => {
+                        let entity = entities.entity(*id);
+                        if let Some(pos) = position.get(entity) {
+                            let idx = map.xy_idx(pos.x, pos.y);
+                            map.index_entity(entity, idx, blockers.contains(entity));
+                        }
+                    }
+                    ComponentEvent::Removed(id) => map.remove_entity_id(*id),
+                }
+            }
+        }
+
+        for trap in map.known_traps.iter_mut() {
+            *trap = false;
+        }
+        for (position, _trigger, _hidden) in (&position, &triggers, !&hidden).join() {
             let idx = map.xy_idx(position.x, position.y);
-            let _p : Option<&BlocksTile> = blockers.get(entity);
-            if let Some(_p) = _p {
-                map.blocked[idx] = true;
+            map.known_traps[idx] = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rltk::RandomNumberGenerator as RNG;
+    use std::time::Instant;
+
+    /// What's on each tile and whether it's blocked.
+    fn snapshot(map: &Map) -> Vec<(Vec<u32>, bool)> {
+        (0..map.tiles.len())
+            .map(|idx| {
+                let mut ids: Vec<u32> = map.entities_at(idx).iter().map(|e| e.id()).collect();
+                ids.sort_unstable();
+                (ids, map.is_blocked(idx))
+            })
+            .collect()
+    }
+
+    /// A rooms map with `count` entities scattered over its floor, one in
+    /// ten of them blocking, already indexed once.
+    struct Crowd {
+        ecs: World,
+        system: MapIndexingSystem,
+        spawned: Vec<Entity>,
+        floor: Vec<usize>,
+        width: i32,
+        rng: RNG,
+    }
+
+    impl Crowd {
+        fn new(count: usize) -> Crowd {
+            let mut ecs = World::new();
+            ecs.register::<Position>();
+            ecs.register::<BlocksTile>();
+            ecs.register::<EntryTrigger>();
+            ecs.register::<Hidden>();
+
+            let map = Map::new_map(1);
+            let floor: Vec<usize> = (0..map.tiles.len()).filter(|idx| map.tiles[*idx].is_walkable()).collect();
+            let width = map.width;
+            let mut rng = RNG::seeded(1);
+            let mut spawned = Vec::new();
+            for i in 0..count {
+                let idx = *rng.random_slice_entry(&floor).unwrap();
+                let mut builder = ecs.create_entity().with(Position { x: idx as i32 % width, y: idx as i32 / width });
+                if i % 10 == 0 {
+                    builder = builder.with(BlocksTile {});
+                }
+                spawned.push(builder.build());
+            }
+            ecs.insert(map);
+            let mut system = MapIndexingSystem::default();
+            system.run_now(&ecs);
+            Crowd { ecs, system, spawned, floor, width, rng }
+        }
+
+        /// Moves 1% of the crowd somewhere random.
+        fn shuffle(&mut self) {
+            let mut positions = self.ecs.write_storage::<Position>();
+            for _ in 0..self.spawned.len() / 100 {
+                let entity = *self.rng.random_slice_entry(&self.spawned).unwrap();
+                let idx = *self.rng.random_slice_entry(&self.floor).unwrap();
+                if let Some(pos) = positions.get_mut(entity) {
+                    pos.x = idx as i32 % self.width;
+                    pos.y = idx as i32 / self.width;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_updates_match_a_full_rebuild() {
+        let mut crowd = Crowd::new(5000);
+        for turn in 0..50 {
+            crowd.shuffle();
+            if turn % 10 == 0 {
+                let victim = crowd.spawned.swap_remove(crowd.rng.range(0, crowd.spawned.len()));
+                crowd.ecs.delete_entity(victim).unwrap();
+                crowd.ecs.maintain();
+            }
+            crowd.system.run_now(&crowd.ecs);
+        }
+
+        let incremental = snapshot(&crowd.ecs.fetch::<Map>());
+        crowd.ecs.fetch_mut::<Map>().indexed = false;
+        crowd.system.run_now(&crowd.ecs);
+        assert!(incremental == snapshot(&crowd.ecs.fetch::<Map>()));
+    }
+
+    /// Times a full rebuild every turn against incremental updates, with 1%
+    /// of 5000 entities moving per turn. Run it with
+    /// `cargo test benchmark -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn benchmark_incremental_against_full_rebuild() {
+        const TURNS: u32 = 200;
+        let mut per_turn = Vec::new();
+        for incremental in [false, true].iter() {
+            let mut crowd = Crowd::new(5000);
+            let start = Instant::now();
+            for _ in 0..TURNS {
+                crowd.shuffle();
+                if !*incremental {
+                    crowd.ecs.fetch_mut::<Map>().indexed = false;
+                }
+                crowd.system.run_now(&crowd.ecs);
             }
-            map.tile_content[idx].push(entity);
+            let elapsed = start.elapsed() / TURNS;
+            println!("{:>11}: 5000 entities, {:?} per turn", if *incremental { "incremental" } else { "full" }, elapsed);
+            per_turn.push(elapsed);
         }
+        assert!(per_turn[1] < per_turn[0], "incremental updates were no faster than rebuilding");
     }
 }
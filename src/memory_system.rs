@@ -0,0 +1,34 @@
+use crate::{Hidden, Map, Player, Position, Renderable};
+use specs::prelude::*;
+
+/// Records what the player can currently see on each visible tile, so the
+/// last sighting can be drawn once the tile drops out of view.
+pub struct MemorySystem {}
+
+impl<'a> System<'a> for MemorySystem {
+    type SystemData = (
+        WriteExpect<'a, Map>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Renderable>,
+        ReadStorage<'a, Hidden>,
+        ReadStorage<'a, Player>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut map, positions, renderables, hidden, players) = data;
+
+        let mut top_order = vec![i32::MAX; map.tiles.len()];
+        for idx in 0..map.tiles.len() {
+            if map.visible_tiles[idx] {
+                map.remembered[idx] = None;
+            }
+        }
+        for (pos, render, _hidden, _player) in (&positions, &renderables, !&hidden, !&players).join() {
+            let idx = map.xy_idx(pos.x, pos.y);
+            if map.visible_tiles[idx] && render.render_order < top_order[idx] {
+                top_order[idx] = render.render_order;
+                map.remembered[idx] = Some((render.glyph, render.fg));
+            }
+        }
+    }
+}
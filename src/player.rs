@@ -1,22 +1,33 @@
 pub use crate::components::*;
 pub use crate::map::*;
-pub use crate::visibility_system::VisibilitySystem;
-use rltk::{console, Point};
+use crate::status_effect_system::confused_step;
+use crate::GameLog;
+use rltk::{console, DijkstraMap, Point, RandomNumberGenerator as RNG};
 use specs::prelude::*;
-use std::cmp::{max, min};
 
 pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
     let mut positions = ecs.write_storage::<Position>();
     let mut players = ecs.write_storage::<Player>();
     let mut viewsheds = ecs.write_storage::<Viewshed>();
     let combat_stats = ecs.read_storage::<CombatStats>();
-    let map = ecs.fetch::<Map>();
+    let mut map = ecs.fetch_mut::<Map>();
     let entities = ecs.entities();
     let mut wants_to_melee = ecs.write_storage::<WantsToMelee>();
+    let status_effects = ecs.read_storage::<StatusEffect>();
 
     for (entity, _player, pos, viewshed) in
         (&entities, &mut players, &mut positions, &mut viewsheds).join()
     {
+        if StatusEffect::has(&status_effects, entity, StatusEffectKind::Stun) {
+            ecs.write_resource::<GameLog>().log("You are stunned and cannot move.");
+            return;
+        }
+        let (delta_x, delta_y) = if StatusEffect::has(&status_effects, entity, StatusEffectKind::Confusion) {
+            confused_step(&mut ecs.write_resource::<RNG>())
+        } else {
+            (delta_x, delta_y)
+        };
+
         if pos.x + delta_x < 1
             || pos.x + delta_x > map.width - 1
             || pos.y + delta_y < 1
@@ -26,10 +37,10 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
         }
 
         let destination_idx = map.xy_idx(pos.x + delta_x, pos.y + delta_y);
-        for potential_target in map.tile_content[destination_idx].iter() {
+        for potential_target in map.entities_at(destination_idx).iter() {
             let target = combat_stats.get(*potential_target);
             if let Some(_t) = target {
-                console::log(&format!("Adding WantsToMelee"));
+                console::log("Adding WantsToMelee");
                 wants_to_melee
                     .insert(entity,
                         WantsToMelee {
@@ -39,13 +50,438 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
                 return;
             }
         }
-        if !map.blocked[destination_idx] {
-            pos.x = min(79, max(0, pos.x + delta_x));
-            pos.y = min(49, max(0, pos.y + delta_y));
+        if map.tiles[destination_idx] == TileType::DoorClosed {
+            map.open_door(destination_idx);
+            viewshed.dirty = true;
+            ecs.write_resource::<GameLog>().log("You open the door.");
+            return;
+        }
+        if !map.is_blocked(destination_idx) {
+            pos.x = (pos.x + delta_x).clamp(0, map.width - 1);
+            pos.y = (pos.y + delta_y).clamp(0, map.height - 1);
+            map.move_entity(entity, destination_idx);
             viewshed.dirty = true;
+            ecs.write_storage::<EntityMoved>()
+                .insert(entity, EntityMoved {})
+                .expect("Unable to insert marker");
             let mut player_pos = ecs.write_resource::<Point>();
             player_pos.x = pos.x;
             player_pos.y = pos.y;
         }
     }
 }
+
+pub fn close_doors(ecs: &mut World) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let positions = ecs.read_storage::<Position>();
+    let mut viewsheds = ecs.write_storage::<Viewshed>();
+    let mut map = ecs.fetch_mut::<Map>();
+    let mut log = ecs.write_resource::<GameLog>();
+
+    let pos = match positions.get(player_entity) {
+        Some(pos) => pos,
+        None => return,
+    };
+    let mut closed = false;
+    for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)].iter() {
+        let idx = map.xy_idx(pos.x + dx, pos.y + dy);
+        closed |= map.close_door(idx);
+    }
+    if closed {
+        log.log("You close the door.");
+        if let Some(viewshed) = viewsheds.get_mut(player_entity) {
+            viewshed.dirty = true;
+        }
+    } else {
+        log.log("There is no open door to close.");
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum LevelUpChoice {
+    ArmorClass,
+    Skill,
+    MaxHp,
+}
+
+pub fn player_has_stat_points(ecs: &World) -> bool {
+    let players = ecs.read_storage::<Player>();
+    let experience = ecs.read_storage::<Experience>();
+    (&players, &experience).join().any(|(_p, exp)| exp.stat_points > 0)
+}
+
+pub fn spend_stat_point(ecs: &mut World, choice: LevelUpChoice) {
+    let players = ecs.read_storage::<Player>();
+    let mut experience = ecs.write_storage::<Experience>();
+    let mut combat_stats = ecs.write_storage::<CombatStats>();
+    let mut log = ecs.write_resource::<GameLog>();
+
+    for (_player, exp, stats) in (&players, &mut experience, &mut combat_stats).join() {
+        if exp.stat_points < 1 {
+            continue;
+        }
+        exp.stat_points -= 1;
+        match choice {
+            LevelUpChoice::ArmorClass => {
+                stats.armor_class += 1;
+                log.log(format!("Your armor class is now {}.", stats.armor_class));
+            }
+            LevelUpChoice::Skill => {
+                stats.skill += 1;
+                log.log(format!("Your skill is now {}.", stats.skill));
+            }
+            LevelUpChoice::MaxHp => {
+                stats.max_hp += 5;
+                stats.hp += 5;
+                log.log(format!("Your max hp is now {}.", stats.max_hp));
+            }
+        }
+    }
+}
+
+pub fn pick_up_item(ecs: &mut World) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let target = {
+        let entities = ecs.entities();
+        let items = ecs.read_storage::<Item>();
+        let positions = ecs.read_storage::<Position>();
+        let player_pos = match positions.get(player_entity) {
+            Some(pos) => (pos.x, pos.y),
+            None => return,
+        };
+        (&entities, &items, &positions)
+            .join()
+            .find(|(_, _, pos)| (pos.x, pos.y) == player_pos)
+            .map(|(item, _, _)| item)
+    };
+    match target {
+        None => ecs.write_resource::<GameLog>().log("There is nothing here to pick up."),
+        Some(item) => take_item(ecs, item),
+    }
+}
+
+/// Moves `item` into the player's hands if it's a weapon, otherwise into
+/// their backpack.
+pub fn take_item(ecs: &mut World, item: Entity) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let entities = ecs.entities();
+    let names = ecs.read_storage::<Name>();
+    let melee_weapons = ecs.read_storage::<MeleeWeapon>();
+    let mut positions = ecs.write_storage::<Position>();
+    let mut backpack = ecs.write_storage::<InBackpack>();
+    let mut equipped = ecs.write_storage::<Equipped>();
+    let mut log = ecs.write_resource::<GameLog>();
+
+    positions.remove(item);
+    if melee_weapons.get(item).is_some() {
+        let wielded: Vec<Entity> = (&entities, &equipped, &melee_weapons)
+            .join()
+            .filter(|(_, eq, _)| eq.owner == player_entity)
+            .map(|(e, _, _)| e)
+            .collect();
+        for old in wielded {
+            equipped.remove(old);
+            backpack
+                .insert(old, InBackpack { owner: player_entity })
+                .expect("Unable to stow weapon");
+        }
+        equipped
+            .insert(item, Equipped { owner: player_entity })
+            .expect("Unable to equip weapon");
+        log.log(format!("You wield the {}.", names.get(item).unwrap().name));
+    } else {
+        backpack
+            .insert(item, InBackpack { owner: player_entity })
+            .expect("Unable to pick up item");
+        log.log(format!("You pick up the {}.", names.get(item).unwrap().name));
+    }
+}
+
+pub fn eat_food(ecs: &mut World) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let entities = ecs.entities();
+    let food = ecs.read_storage::<ProvidesFood>();
+    let backpack = ecs.read_storage::<InBackpack>();
+    let names = ecs.read_storage::<Name>();
+    let mut clocks = ecs.write_storage::<HungerClock>();
+    let mut combat_stats = ecs.write_storage::<CombatStats>();
+    let mut log = ecs.write_resource::<GameLog>();
+
+    let meal = (&entities, &food, &backpack)
+        .join()
+        .find(|(_, _, pack)| pack.owner == player_entity)
+        .map(|(item, _, _)| item);
+    match (meal, clocks.get_mut(player_entity), combat_stats.get_mut(player_entity)) {
+        (Some(meal), Some(clock), Some(stats)) => {
+            clock.set_state(HungerState::WellFed, stats);
+            log.log(format!("You eat the {}.", names.get(meal).unwrap().name));
+            entities.delete(meal).expect("Unable to delete food");
+        }
+        _ => log.log("You have nothing to eat."),
+    }
+}
+
+pub fn drink_potion(ecs: &mut World) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let entities = ecs.entities();
+    let healing = ecs.read_storage::<ProvidesHealing>();
+    let backpack = ecs.read_storage::<InBackpack>();
+    let names = ecs.read_storage::<Name>();
+    let mut combat_stats = ecs.write_storage::<CombatStats>();
+    let mut log = ecs.write_resource::<GameLog>();
+
+    let potion = (&entities, &healing, &backpack)
+        .join()
+        .find(|(_, _, pack)| pack.owner == player_entity)
+        .map(|(item, healing, _)| (item, healing.heal_amount));
+    match (potion, combat_stats.get_mut(player_entity)) {
+        (Some((potion, heal_amount)), Some(stats)) => {
+            stats.hp = i32::min(stats.max_hp, stats.hp + heal_amount);
+            log.log(format!("You drink the {}, healing {} hp.", names.get(potion).unwrap().name, heal_amount));
+            entities.delete(potion).expect("Unable to delete potion");
+        }
+        _ => log.log("You have nothing to drink."),
+    }
+}
+
+/// First enemy standing on a tile the player can currently see.
+pub fn visible_hostile(
+    map: &Map,
+    entities: &Entities,
+    positions: &ReadStorage<Position>,
+    enemies: &ReadStorage<Enemy>,
+) -> Option<Entity> {
+    (entities, positions, enemies)
+        .join()
+        .find(|(_, pos, _)| map.visible_tiles[map.xy_idx(pos.x, pos.y)])
+        .map(|(entity, _, _)| entity)
+}
+
+pub fn visible_hostiles(ecs: &World) -> Vec<Entity> {
+    let map = ecs.fetch::<Map>();
+    let entities = ecs.entities();
+    let positions = ecs.read_storage::<Position>();
+    let enemies = ecs.read_storage::<Enemy>();
+    (&entities, &positions, &enemies)
+        .join()
+        .filter(|(_, pos, _)| map.visible_tiles[map.xy_idx(pos.x, pos.y)])
+        .map(|(entity, _, _)| entity)
+        .collect()
+}
+
+pub fn hostile_in_view(ecs: &World) -> Option<Entity> {
+    visible_hostile(
+        &ecs.fetch::<Map>(),
+        &ecs.entities(),
+        &ecs.read_storage::<Position>(),
+        &ecs.read_storage::<Enemy>(),
+    )
+}
+
+pub fn name_of(ecs: &World, entity: Entity) -> String {
+    let names = ecs.read_storage::<Name>();
+    names.get(entity).map_or("something".to_string(), |n| n.name.clone())
+}
+
+pub enum RestInterruption {
+    Hostile(String),
+    Healed,
+    Starving,
+}
+
+/// Why the player can't (keep) resting, if anything.
+pub fn rest_interruption(ecs: &World) -> Option<RestInterruption> {
+    let player_entity = *ecs.fetch::<Entity>();
+    if let Some(hostile) = hostile_in_view(ecs) {
+        return Some(RestInterruption::Hostile(name_of(ecs, hostile)));
+    }
+    let clocks = ecs.read_storage::<HungerClock>();
+    if clocks.get(player_entity).is_some_and(|c| c.state == HungerState::Starving) {
+        return Some(RestInterruption::Starving);
+    }
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    match combat_stats.get(player_entity) {
+        Some(stats) if stats.hp < stats.max_hp => None,
+        _ => Some(RestInterruption::Healed),
+    }
+}
+
+pub fn player_hp(ecs: &World) -> i32 {
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    combat_stats.get(*ecs.fetch::<Entity>()).map_or(0, |stats| stats.hp)
+}
+
+/// Items lying on tiles the player can currently see.
+pub fn visible_items(ecs: &World) -> Vec<Entity> {
+    let map = ecs.fetch::<Map>();
+    let entities = ecs.entities();
+    let items = ecs.read_storage::<Item>();
+    let positions = ecs.read_storage::<Position>();
+    (&entities, &items, &positions)
+        .join()
+        .filter(|(_, _, pos)| map.visible_tiles[map.xy_idx(pos.x, pos.y)])
+        .map(|(entity, _, _)| entity)
+        .collect()
+}
+
+/// The next step towards the nearest reachable tile the player has never
+/// seen, or None once there is nothing left to explore.
+pub fn explore_step(ecs: &World) -> Option<(i32, i32)> {
+    const MAX_DEPTH: f32 = 2000.0;
+
+    let map = ecs.fetch::<Map>();
+    let player_pos = ecs.fetch::<Point>();
+    let targets: Vec<usize> = (0..map.tiles.len())
+        .filter(|idx| !map.revealed_tiles[*idx] && map.tiles[*idx].is_walkable())
+        .collect();
+    if targets.is_empty() {
+        return None;
+    }
+    let dijkstra = DijkstraMap::new(map.width, map.height, &targets, &*map, MAX_DEPTH);
+    let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+    let next = DijkstraMap::find_lowest_exit(&dijkstra, player_idx, &*map)?;
+    if dijkstra.map[next] >= dijkstra.map[player_idx] {
+        return None;
+    }
+    Some(step_towards(&map, *player_pos, next))
+}
+
+fn step_towards(map: &Map, from: Point, to: usize) -> (i32, i32) {
+    (to as i32 % map.width - from.x, to as i32 / map.width - from.y)
+}
+
+/// The next step of the shortest path to `target`, if there is one.
+pub fn travel_step(ecs: &World, target: usize) -> Option<(i32, i32)> {
+    let map = ecs.fetch::<Map>();
+    let player_pos = ecs.fetch::<Point>();
+    let path = rltk::a_star_search(map.xy_idx(player_pos.x, player_pos.y) as i32, target as i32, &*map);
+    if path.success && path.steps.len() > 1 {
+        Some(step_towards(&map, *player_pos, path.steps[1]))
+    } else {
+        None
+    }
+}
+
+/// Where a run carries on after arriving on the player's tile by `(dx, dy)`.
+/// In a corridor the run follows its bends and stops where it opens up; in
+/// the open it keeps straight on. Doors and anything in the way stop it.
+/// Returns the next direction and whether that step is along a corridor.
+pub fn run_step(ecs: &World, dx: i32, dy: i32, corridor: bool) -> Option<(i32, i32, bool)> {
+    let map = ecs.fetch::<Map>();
+    let player_pos = ecs.fetch::<Point>();
+    let tile_at = |(x, y): (i32, i32)| {
+        let (x, y) = (player_pos.x + x, player_pos.y + y);
+        if x < 0 || x >= map.width || y < 0 || y >= map.height {
+            None
+        } else {
+            Some(map.xy_idx(x, y))
+        }
+    };
+    let is_door = |idx: usize| matches!(map.tiles[idx], TileType::DoorOpen | TileType::DoorClosed);
+
+    let here = map.xy_idx(player_pos.x, player_pos.y);
+    if is_door(here) {
+        return None;
+    }
+    let exits: Vec<((i32, i32), usize)> = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+        .iter()
+        .filter(|delta| **delta != (-dx, -dy))
+        .filter_map(|delta| tile_at(*delta).map(|idx| (*delta, idx)))
+        .filter(|(_, idx)| map.tiles[*idx].is_walkable() || map.tiles[*idx] == TileType::DoorClosed)
+        .collect();
+    if exits.iter().any(|(_, idx)| is_door(*idx)) {
+        return None;
+    }
+    let next = match exits.len() {
+        0 => None,
+        1 => Some((exits[0].0, true)),
+        _ if corridor => None,
+        _ => exits.iter().find(|(delta, _)| *delta == (dx, dy)).map(|(delta, _)| (*delta, false)),
+    };
+    next.filter(|(delta, _)| tile_at(*delta).is_some_and(|idx| !map.is_blocked(idx)))
+        .map(|((dx, dy), corridor)| (dx, dy, corridor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drinking_a_potion_heals_and_uses_it_up() {
+        let mut ecs = World::new();
+        ecs.register::<CombatStats>();
+        ecs.register::<ProvidesHealing>();
+        ecs.register::<InBackpack>();
+        ecs.register::<Name>();
+        ecs.insert(GameLog { entries: Vec::new() });
+        let stats = CombatStats { max_hp: 20, hp: 5, armor_class: 10, skill: 0, damage: rltk::DiceType::new(1, 4, 0) };
+        let player = ecs.create_entity().with(stats).build();
+        ecs.insert(player);
+        let potion = ecs
+            .create_entity()
+            .with(ProvidesHealing { heal_amount: 8 })
+            .with(InBackpack { owner: player })
+            .with(Name { name: "Health Potion".to_string() })
+            .build();
+
+        drink_potion(&mut ecs);
+        ecs.maintain();
+        assert_eq!(ecs.read_storage::<CombatStats>().get(player).unwrap().hp, 13);
+        assert!(!ecs.is_alive(potion));
+
+        drink_potion(&mut ecs);
+        assert_eq!(ecs.fetch::<GameLog>().entries.last().unwrap(), "You have nothing to drink.");
+    }
+
+    #[test]
+    fn wielded_weapons_replace_bare_handed_damage() {
+        use crate::combat_system::MeleeCombatSystem;
+        let mut ecs = World::new();
+        ecs.register::<CombatStats>();
+        ecs.register::<Name>();
+        ecs.register::<Position>();
+        ecs.register::<InBackpack>();
+        ecs.register::<Equipped>();
+        ecs.register::<MeleeWeapon>();
+        ecs.register::<WantsToMelee>();
+        ecs.register::<SufferDamage>();
+        ecs.register::<InflictsStatus>();
+        ecs.register::<StatusEffect>();
+        ecs.insert(GameLog { entries: Vec::new() });
+        ecs.insert(RNG::seeded(1));
+        // Never misses and does 1 hp bare handed
+        let stats = CombatStats { max_hp: 20, hp: 20, armor_class: 10, skill: 100, damage: rltk::DiceType::new(1, 1, 0) };
+        let player = ecs.create_entity().with(stats).with(Name { name: "Player".to_string() }).build();
+        ecs.insert(player);
+        let weapon = |ecs: &mut World, name: &str, bonus: i32| {
+            ecs.create_entity()
+                .with(Name { name: name.to_string() })
+                .with(Position { x: 1, y: 1 })
+                .with(MeleeWeapon { damage: rltk::DiceType::new(1, 1, bonus) })
+                .build()
+        };
+        let dagger = weapon(&mut ecs, "Dagger", 2);
+        let sword = weapon(&mut ecs, "Sword", 9);
+
+        take_item(&mut ecs, dagger);
+        take_item(&mut ecs, sword);
+        assert_eq!(ecs.read_storage::<Equipped>().get(sword).map(|e| e.owner), Some(player));
+        assert!(!ecs.read_storage::<Equipped>().contains(dagger));
+        assert_eq!(ecs.read_storage::<InBackpack>().get(dagger).map(|b| b.owner), Some(player));
+        assert!(!ecs.read_storage::<Position>().contains(sword));
+
+        let stats = CombatStats { max_hp: 50, hp: 50, armor_class: 10, skill: 0, damage: rltk::DiceType::new(1, 1, 0) };
+        let rat = ecs.create_entity().with(stats).with(Name { name: "Rat".to_string() }).build();
+        // A natural 1 fumbles, so swing until something lands
+        for _ in 0..20 {
+            ecs.write_storage::<WantsToMelee>().insert(player, WantsToMelee { target: rat }).unwrap();
+            MeleeCombatSystem {}.run_now(&ecs);
+            if ecs.read_storage::<SufferDamage>().contains(rat) {
+                break;
+            }
+        }
+        let damage = ecs.read_storage::<SufferDamage>().get(rat).map(|d| d.amount.clone());
+        // 10, or 20 on a critical
+        assert!(matches!(damage.as_deref(), Some([10]) | Some([20])), "sword did {:?}", damage);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{Map, Rect, TileType};
+use rltk::RandomNumberGenerator as RNG;
+
+/// Hand-drawn pieces of map. A space leaves whatever was already there.
+pub const VAULTS: &[(&str, &str)] = &[
+    ("goblin_den", include_str!("../prefabs/goblin_den.txt")),
+    ("treasury", include_str!("../prefabs/treasury.txt")),
+    ("shrine", include_str!("../prefabs/shrine.txt")),
+];
+
+/// Prefabs big enough to be a level on their own.
+pub const LEVELS: &[(&str, &str)] = &[("arena", include_str!("../prefabs/arena.txt"))];
+
+pub fn vault(name: &str) -> Option<Prefab> {
+    VAULTS.iter().chain(LEVELS.iter()).find(|(n, _)| *n == name).map(|(_, text)| Prefab::parse(text))
+}
+
+/// What a prefab character turns into: a tile, plus anything to spawn on it.
+fn legend(c: char) -> Option<(TileType, Option<&'static str>)> {
+    match c {
+        '#' => Some((TileType::Wall, None)),
+        '.' => Some((TileType::Floor, None)),
+        '+' => Some((TileType::DoorClosed, None)),
+        'g' => Some((TileType::Floor, Some("Goblin"))),
+        '!' => Some((TileType::Floor, Some("Health Potion"))),
+        _ => None,
+    }
+}
+
+#[derive(Clone)]
+pub struct Prefab {
+    pub width: i32,
+    pub height: i32,
+    cells: Vec<char>,
+}
+
+impl Prefab {
+    pub fn parse(text: &str) -> Prefab {
+        let lines: Vec<&str> = text.lines().collect();
+        let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as i32;
+        let mut cells = Vec::new();
+        for line in lines.iter() {
+            cells.extend(line.chars());
+            cells.extend(std::iter::repeat_n(' ', width as usize - line.chars().count()));
+        }
+        Prefab { width, height: lines.len() as i32, cells }
+    }
+
+    fn at(&self, x: i32, y: i32) -> char {
+        self.cells[(y * self.width + x) as usize]
+    }
+
+    fn remap(&self, width: i32, height: i32, source: impl Fn(i32, i32) -> (i32, i32)) -> Prefab {
+        let mut cells = Vec::with_capacity(self.cells.len());
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = source(x, y);
+                cells.push(self.at(sx, sy));
+            }
+        }
+        Prefab { width, height, cells }
+    }
+
+    /// Turned a quarter clockwise.
+    pub fn rotated(&self) -> Prefab {
+        self.remap(self.height, self.width, |x, y| (y, self.height - 1 - x))
+    }
+
+    /// Flipped left to right.
+    pub fn mirrored(&self) -> Prefab {
+        self.remap(self.width, self.height, |x, y| (self.width - 1 - x, y))
+    }
+
+    /// One of the prefab's eight orientations, picked at random.
+    pub fn random_orientation(&self, rng: &mut RNG) -> Prefab {
+        let mut prefab = self.clone();
+        for _ in 0..rng.range(0, 4) {
+            prefab = prefab.rotated();
+        }
+        if rng.range(0, 2) == 1 {
+            prefab = prefab.mirrored();
+        }
+        prefab
+    }
+
+    /// Overlays the prefab with its top left corner at `(x, y)` and queues
+    /// its spawn markers on the map. Refuses if it would leave the map.
+    pub fn stamp(&self, map: &mut Map, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x + self.width > map.width || y + self.height > map.height {
+            return false;
+        }
+        for py in 0..self.height {
+            for px in 0..self.width {
+                if let Some((tile, spawn)) = legend(self.at(px, py)) {
+                    let idx = map.xy_idx(x + px, y + py);
+                    map.tiles[idx] = tile;
+                    if let Some(name) = spawn {
+                        map.spawns.push(((x + px, y + py), name));
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Stamps the prefab in the middle of `room`'s floor, if it fits.
+    pub fn stamp_in_room(&self, map: &mut Map, room: &Rect) -> bool {
+        let (floor_w, floor_h) = (room.x2 - room.x1, room.y2 - room.y1);
+        if self.width > floor_w || self.height > floor_h {
+            return false;
+        }
+        let x = room.x1 + 1 + (floor_w - self.width) / 2;
+        let y = room.y1 + 1 + (floor_h - self.height) / 2;
+        self.stamp(map, x, y)
+    }
+
+    /// A map made of just this prefab, with the whole thing as its one room.
+    pub fn level(&self) -> Map {
+        let mut map = Map::filled(self.width, self.height, TileType::Wall);
+        self.stamp(&mut map, 0, 0);
+        map.rooms.push(Rect::new(0, 0, self.width - 2, self.height - 2));
+        map
+    }
+}
@@ -0,0 +1,93 @@
+use rltk::{Rltk, RGB};
+use specs::prelude::*;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Number of recent samples the on-screen averages are taken over.
+const WINDOW: usize = 60;
+
+struct Timings {
+    name: &'static str,
+    recent: VecDeque<Duration>,
+    total: Duration,
+    max: Duration,
+    count: u32,
+}
+
+impl Timings {
+    fn average(&self) -> Duration {
+        self.recent.iter().sum::<Duration>() / self.recent.len().max(1) as u32
+    }
+}
+
+/// Per-system run times, in the order the systems were first recorded.
+#[derive(Default)]
+pub struct Profiler {
+    timings: Vec<Timings>,
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl Profiler {
+    pub fn record(&mut self, name: &'static str, duration: Duration) {
+        let index = match self.timings.iter().position(|t| t.name == name) {
+            Some(index) => index,
+            None => {
+                self.timings.push(Timings {
+                    name,
+                    recent: VecDeque::with_capacity(WINDOW),
+                    total: Duration::default(),
+                    max: Duration::default(),
+                    count: 0,
+                });
+                self.timings.len() - 1
+            }
+        };
+        let timings = &mut self.timings[index];
+        if timings.recent.len() == WINDOW {
+            timings.recent.pop_front();
+        }
+        timings.recent.push_back(duration);
+        timings.total += duration;
+        timings.max = timings.max.max(duration);
+        timings.count += 1;
+    }
+
+    /// Runs `system` once and records how long it took under its type name.
+    pub fn run<S>(&mut self, system: &mut S, ecs: &World)
+    where
+        S: for<'a> RunNow<'a>,
+    {
+        let start = Instant::now();
+        system.run_now(ecs);
+        let name = std::any::type_name::<S>().rsplit("::").next().unwrap_or("?");
+        self.record(name, start.elapsed());
+    }
+
+    pub fn draw(&self, ctx: &mut Rltk) {
+        let black = RGB::named(rltk::BLACK);
+        let top = 42 - self.timings.len() as i32 - 1;
+        ctx.draw_box(48, top, 31, self.timings.len() as i32 + 1, RGB::named(rltk::WHITE), black);
+        ctx.print_color(50, top, RGB::named(rltk::YELLOW), black, " Profiler (avg ms) ");
+        for (y, timings) in (top + 1..).zip(self.timings.iter()) {
+            ctx.print(49, y, format!("{:<22}{:>7.3}", timings.name, ms(timings.average())));
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = format!("{:<24}{:>10}{:>10}{:>12}\n", "system", "avg ms", "max ms", "total ms");
+        for timings in self.timings.iter() {
+            let average = timings.total / timings.count.max(1);
+            report += &format!(
+                "{:<24}{:>10.4}{:>10.4}{:>12.2}\n",
+                timings.name,
+                ms(average),
+                ms(timings.max),
+                ms(timings.total)
+            );
+        }
+        report
+    }
+}
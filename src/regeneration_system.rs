@@ -0,0 +1,42 @@
+use crate::player::visible_hostile;
+use crate::{CombatStats, Enemy, HungerClock, HungerState, Map, NaturalRegeneration, Position};
+use specs::prelude::*;
+
+/// Turns per hp healed while an enemy is in view, and while none is.
+const IN_SIGHT_INTERVAL: i32 = 20;
+const OUT_OF_SIGHT_INTERVAL: i32 = 5;
+
+/// Heals the player slowly over time. Sight of enemies is judged from the
+/// map's visible tiles, so this only makes sense on the player.
+pub struct RegenerationSystem {}
+
+impl<'a> System<'a> for RegenerationSystem {
+    type SystemData = (
+        ReadExpect<'a, Map>,
+        Entities<'a>,
+        WriteStorage<'a, NaturalRegeneration>,
+        WriteStorage<'a, CombatStats>,
+        ReadStorage<'a, HungerClock>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Enemy>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (map, entities, mut regeneration, mut combat_stats, clocks, positions, enemies) = data;
+
+        let interval = match visible_hostile(&map, &entities, &positions, &enemies) {
+            Some(_) => IN_SIGHT_INTERVAL,
+            None => OUT_OF_SIGHT_INTERVAL,
+        };
+        for (entity, regen, stats) in (&entities, &mut regeneration, &mut combat_stats).join() {
+            if clocks.get(entity).is_some_and(|c| c.state == HungerState::Starving) {
+                continue;
+            }
+            regen.counter += 1;
+            if regen.counter >= interval {
+                regen.counter = 0;
+                stats.hp = i32::min(stats.max_hp, stats.hp + 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use crate::{Map, Rect, TileType};
+use rltk::RandomNumberGenerator as RNG;
+use rltk::{LineAlg, Point};
+use std::cmp::{max, min};
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum RoomShape {
+    Rectangle,
+    Circle,
+    Cross,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Placement {
+    /// `max_rooms` tries at a random spot, skipping any that overlap.
+    Scatter,
+    /// Splits the map in two again and again, then puts a room in each part.
+    Bsp,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Connection {
+    /// Each room to the one placed before it.
+    Sequential,
+    /// A minimum spanning tree over the room centres, plus `extra_loops`
+    /// links from random rooms to their nearest unlinked neighbour.
+    SpanningTree { extra_loops: usize },
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum CorridorStyle {
+    /// One horizontal and one vertical leg, in either order.
+    LShaped,
+    /// A Bresenham line, widened wherever it steps diagonally.
+    Straight,
+    /// Halfway along the longer axis, across, then the rest of the way.
+    DogLeg,
+}
+
+/// Knobs for the rooms and corridors generator. Shapes and corridor styles
+/// are picked at random from their lists for each room or corridor.
+pub struct RoomsConfig {
+    pub placement: Placement,
+    pub max_rooms: i32,
+    pub min_size: i32,
+    pub max_size: i32,
+    pub shapes: &'static [RoomShape],
+    pub connection: Connection,
+    pub corridors: &'static [CorridorStyle],
+    pub corridor_width: i32,
+}
+
+impl Default for RoomsConfig {
+    fn default() -> RoomsConfig {
+        RoomsConfig {
+            placement: Placement::Scatter,
+            max_rooms: 30,
+            min_size: 6,
+            max_size: 10,
+            shapes: &[RoomShape::Rectangle],
+            connection: Connection::Sequential,
+            corridors: &[CorridorStyle::LShaped],
+            corridor_width: 1,
+        }
+    }
+}
+
+/// Picks from `options`, leaving the RNG alone when there's only one so
+/// single-choice configs generate the same maps they always have.
+fn pick<T: Copy>(rng: &mut RNG, options: &[T]) -> T {
+    match options {
+        [only] => *only,
+        _ => options[rng.range(0, options.len())],
+    }
+}
+
+/// Splits `area` until its parts are too small to hold two rooms, then
+/// fits a room in each. Parts come out depth first, so rooms that follow
+/// each other are neighbours. Like a room, a part's usable tiles run from
+/// `x1 + 1` to `x2`.
+fn bsp_rooms(rng: &mut RNG, config: &RoomsConfig, area: Rect) -> Vec<Rect> {
+    // Room floor plus the wall between it and the next part
+    let smallest = config.min_size + 1;
+    let (w, h) = (area.x2 - area.x1, area.y2 - area.y1);
+    let fits_two = |len: i32| len >= smallest * 2;
+    let small_enough = w <= config.max_size && h <= config.max_size;
+    if (!fits_two(w) && !fits_two(h)) || (small_enough && rng.range(0, 2) == 0) {
+        let rw = rng.range(config.min_size, min(config.max_size, w) + 1);
+        let rh = rng.range(config.min_size, min(config.max_size, h) + 1);
+        let x = rng.range(area.x1, area.x2 - rw + 1);
+        let y = rng.range(area.y1, area.y2 - rh + 1);
+        return vec![Rect::new(x, y, rw, rh)];
+    }
+    let across = fits_two(w) && (!fits_two(h) || w > h || (w == h && rng.range(0, 2) == 0));
+    let (first, second) = if across {
+        let split = area.x1 + rng.range(smallest, w - smallest + 1);
+        (Rect { x2: split - 1, ..area }, Rect { x1: split, ..area })
+    } else {
+        let split = area.y1 + rng.range(smallest, h - smallest + 1);
+        (Rect { y2: split - 1, ..area }, Rect { y1: split, ..area })
+    };
+    let mut rooms = bsp_rooms(rng, config, first);
+    rooms.extend(bsp_rooms(rng, config, second));
+    rooms
+}
+
+fn distance(a: &Rect, b: &Rect) -> i32 {
+    let ((ax, ay), (bx, by)) = (a.center(), b.center());
+    (ax - bx).pow(2) + (ay - by).pow(2)
+}
+
+impl Map {
+    pub fn rooms_map(seed: u64, config: &RoomsConfig) -> Map {
+        let mut rng = RNG::seeded(seed);
+        let mut map = Map::filled(80, 50, TileType::Wall);
+        map.seed = seed;
+
+        match config.placement {
+            Placement::Scatter => {
+                for _ in 0..config.max_rooms {
+                    let w = rng.range(config.min_size, config.max_size);
+                    let h = rng.range(config.min_size, config.max_size);
+                    let x = rng.roll_dice(1, map.width - w - 1) - 1;
+                    let y = rng.roll_dice(1, map.height - h - 1) - 1;
+                    let new_room = Rect::new(x, y, w, h);
+                    if map.rooms.iter().any(|other_room| new_room.intersect(other_room)) {
+                        continue;
+                    }
+                    map.place_room(&mut rng, config, new_room);
+                }
+            }
+            Placement::Bsp => {
+                let whole = Rect { x1: 0, y1: 0, x2: map.width - 2, y2: map.height - 2 };
+                for new_room in bsp_rooms(&mut rng, config, whole) {
+                    map.place_room(&mut rng, config, new_room);
+                }
+            }
+        }
+        if let Connection::SpanningTree { extra_loops } = config.connection {
+            map.connect_spanning_tree(&mut rng, config, extra_loops);
+        }
+
+        let vault_room = map.add_vault(&mut rng);
+        map.place_doors();
+        map.add_terrain_features(&mut rng, vault_room);
+        map.connect_regions();
+        map
+    }
+
+    /// Rooms packed by binary space partitioning, so they fill the map
+    /// evenly, each joined to the next.
+    pub fn bsp_map(seed: u64) -> Map {
+        let config = RoomsConfig { placement: Placement::Bsp, ..RoomsConfig::default() };
+        Map::rooms_map(seed, &config)
+    }
+
+    /// Round rooms, crosses, several corridor styles and a few loops.
+    pub fn varied_rooms_map(seed: u64) -> Map {
+        let config = RoomsConfig {
+            shapes: &[RoomShape::Rectangle, RoomShape::Circle, RoomShape::Cross],
+            connection: Connection::SpanningTree { extra_loops: 4 },
+            corridors: &[CorridorStyle::LShaped, CorridorStyle::Straight, CorridorStyle::DogLeg],
+            ..RoomsConfig::default()
+        };
+        Map::rooms_map(seed, &config)
+    }
+
+    /// Fewer, bigger rooms joined by two tile wide dog-legs.
+    pub fn wide_rooms_map(seed: u64) -> Map {
+        let config = RoomsConfig {
+            placement: Placement::Scatter,
+            max_rooms: 20,
+            min_size: 8,
+            max_size: 14,
+            shapes: &[RoomShape::Rectangle, RoomShape::Circle],
+            connection: Connection::SpanningTree { extra_loops: 2 },
+            corridors: &[CorridorStyle::DogLeg],
+            corridor_width: 2,
+        };
+        Map::rooms_map(seed, &config)
+    }
+
+    fn place_room(&mut self, rng: &mut RNG, config: &RoomsConfig, new_room: Rect) {
+        let shape = pick(rng, config.shapes);
+        self.add_room(&new_room, shape);
+        if config.connection == Connection::Sequential {
+            if let Some(prev) = self.rooms.last().copied() {
+                self.add_corridor(rng, config, prev.center(), new_room.center());
+            }
+        }
+        self.rooms.push(new_room);
+    }
+
+    /// Digs out the floor of `room`. Every shape covers the room's centre,
+    /// which is where corridors are dug to. Rectangles and crosses also
+    /// cover the whole middle row and column, but a circle's diameter is
+    /// the shorter side, so in long rooms it stops short of their ends.
+    fn add_room(&mut self, room: &Rect, shape: RoomShape) {
+        let (cx, cy) = room.center();
+        let (w, h) = (room.x2 - room.x1, room.y2 - room.y1);
+        for y in room.y1 + 1..=room.y2 {
+            for x in room.x1 + 1..=room.x2 {
+                let inside = match shape {
+                    RoomShape::Rectangle => true,
+                    RoomShape::Circle => {
+                        let radius = min(w, h) as f32 / 2.0;
+                        rltk::DistanceAlg::Pythagoras.distance2d(Point::new(x, y), Point::new(cx, cy)) <= radius
+                    }
+                    RoomShape::Cross => (x - cx).abs() <= w / 6 || (y - cy).abs() <= h / 6,
+                };
+                if inside {
+                    let idx = self.xy_idx(x, y);
+                    self.tiles[idx] = TileType::Floor;
+                }
+            }
+        }
+    }
+
+    /// Prim's algorithm over the room centres, then the extra loops.
+    fn connect_spanning_tree(&mut self, rng: &mut RNG, config: &RoomsConfig, extra_loops: usize) {
+        let rooms = self.rooms.clone();
+        if rooms.len() < 2 {
+            return;
+        }
+        let mut linked = vec![vec![false; rooms.len()]; rooms.len()];
+        let mut in_tree = vec![false; rooms.len()];
+        in_tree[0] = true;
+        for _ in 1..rooms.len() {
+            let closest = (0..rooms.len())
+                .filter(|a| in_tree[*a])
+                .flat_map(|a| (0..rooms.len()).filter(|b| !in_tree[*b]).map(move |b| (a, b)))
+                .min_by_key(|(a, b)| distance(&rooms[*a], &rooms[*b]));
+            if let Some((a, b)) = closest {
+                in_tree[b] = true;
+                linked[a][b] = true;
+                linked[b][a] = true;
+                self.add_corridor(rng, config, rooms[a].center(), rooms[b].center());
+            }
+        }
+        for _ in 0..extra_loops {
+            let a = rng.range(0, rooms.len());
+            let nearest = (0..rooms.len())
+                .filter(|b| *b != a && !linked[a][*b])
+                .min_by_key(|b| distance(&rooms[a], &rooms[*b]));
+            if let Some(b) = nearest {
+                linked[a][b] = true;
+                linked[b][a] = true;
+                self.add_corridor(rng, config, rooms[a].center(), rooms[b].center());
+            }
+        }
+    }
+
+    fn add_corridor(&mut self, rng: &mut RNG, config: &RoomsConfig, from: (i32, i32), to: (i32, i32)) {
+        let ((x1, y1), (x2, y2)) = (from, to);
+        let width = config.corridor_width;
+        match pick(rng, config.corridors) {
+            CorridorStyle::LShaped => {
+                if rng.range(0, 2) == 1 {
+                    self.add_horizontal_tunnel(x1, x2, y1, width);
+                    self.add_vertical_tunnel(y1, y2, x2, width);
+                } else {
+                    self.add_vertical_tunnel(y1, y2, x1, width);
+                    self.add_horizontal_tunnel(x1, x2, y2, width);
+                }
+            }
+            CorridorStyle::Straight => {
+                let line = rltk::line2d(LineAlg::Bresenham, Point::new(x1, y1), Point::new(x2, y2));
+                for (i, point) in line.iter().enumerate() {
+                    self.dig(point.x, point.y, width);
+                    // Fill the corner of diagonal steps so it can be walked
+                    if let Some(prev) = i.checked_sub(1).map(|i| line[i]) {
+                        self.dig(point.x, prev.y, width);
+                    }
+                }
+            }
+            CorridorStyle::DogLeg => {
+                if (x2 - x1).abs() >= (y2 - y1).abs() {
+                    let mid = (x1 + x2) / 2;
+                    self.add_horizontal_tunnel(x1, mid, y1, width);
+                    self.add_vertical_tunnel(y1, y2, mid, width);
+                    self.add_horizontal_tunnel(mid, x2, y2, width);
+                } else {
+                    let mid = (y1 + y2) / 2;
+                    self.add_vertical_tunnel(y1, mid, x1, width);
+                    self.add_horizontal_tunnel(x1, x2, mid, width);
+                    self.add_vertical_tunnel(mid, y2, x2, width);
+                }
+            }
+        }
+    }
+
+    /// Floors a `width` square with its top left at `(x, y)`, short of the
+    /// map's outer edge.
+    fn dig(&mut self, x: i32, y: i32, width: i32) {
+        for dy in 0..width {
+            for dx in 0..width {
+                let (x, y) = (x + dx, y + dy);
+                if x > 0 && x < self.width - 1 && y > 0 && y < self.height - 1 {
+                    let idx = self.xy_idx(x, y);
+                    self.tiles[idx] = TileType::Floor;
+                }
+            }
+        }
+    }
+
+    fn add_horizontal_tunnel(&mut self, x1: i32, x2: i32, y: i32, width: i32) {
+        for x in min(x1, x2)..=max(x1, x2) {
+            self.dig(x, y, width);
+        }
+    }
+
+    fn add_vertical_tunnel(&mut self, y1: i32, y2: i32, x: i32, width: i32) {
+        for y in min(y1, y2)..=max(y1, y2) {
+            self.dig(x, y, width);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bsp_rooms_fill_the_map_without_overlapping() {
+        for seed in 0..20 {
+            let mut rng = RNG::seeded(seed);
+            let config = RoomsConfig { placement: Placement::Bsp, ..RoomsConfig::default() };
+            let rooms = bsp_rooms(&mut rng, &config, Rect { x1: 0, y1: 0, x2: 78, y2: 48 });
+            assert!(rooms.len() >= 10, "seed {} only made {} rooms", seed, rooms.len());
+            for (i, room) in rooms.iter().enumerate() {
+                assert!(room.x1 >= 0 && room.x2 <= 78 && room.y1 >= 0 && room.y2 <= 48);
+                assert!(room.x2 - room.x1 >= config.min_size && room.y2 - room.y1 >= config.min_size);
+                assert!(rooms[i + 1..].iter().all(|other| !room.intersect(other)));
+            }
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use crate::{
+    BlocksTile, CombatStats, Corpse, Enemy, EntryTrigger, Equipped, ExperienceValue, Hidden,
+    InflictsDamage, InflictsStatus, Item, LightSource, LootTable, Map, MeleeWeapon, Name, Position,
+    ProvidesFood, ProvidesHealing, Rect, Renderable, StatusEffectKind, Teleports, Viewshed,
+};
+use rltk::{DiceType, RandomNumberGenerator as RNG, RGB};
+use specs::prelude::*;
+use std::sync::OnceLock;
+
+pub struct MonsterTemplate {
+    pub name: String,
+    pub glyph: char,
+    pub color: (u8, u8, u8),
+    pub max_hp: i32,
+    pub armor_class: i32,
+    pub skill: i32,
+    pub damage: DiceType,
+    pub xp: i32,
+    pub inflicts: Option<(StatusEffectKind, i32)>,
+    pub equipped: Option<String>,
+    pub loot: Vec<(String, i32)>,
+}
+
+pub struct ItemTemplate {
+    pub name: &'static str,
+    pub glyph: char,
+    pub color: (u8, u8, u8),
+    pub melee_damage: Option<&'static str>,
+    pub food: bool,
+    pub healing: Option<i32>,
+}
+
+pub struct TrapTemplate {
+    pub name: &'static str,
+    pub damage: Option<&'static str>,
+    pub inflicts: Option<(StatusEffectKind, i32)>,
+    pub teleports: bool,
+}
+
+/// Every monster, as described in `assets/monsters.txt`.
+pub fn monsters() -> &'static [MonsterTemplate] {
+    static MONSTERS: OnceLock<Vec<MonsterTemplate>> = OnceLock::new();
+    MONSTERS.get_or_init(|| parse_monsters(include_str!("../assets/monsters.txt")).expect("Bad monster data"))
+}
+
+fn parse_status(word: &str) -> Option<StatusEffectKind> {
+    match word {
+        "poison" => Some(StatusEffectKind::Poison),
+        "confusion" => Some(StatusEffectKind::Confusion),
+        "stun" => Some(StatusEffectKind::Stun),
+        "regeneration" => Some(StatusEffectKind::Regeneration),
+        _ => None,
+    }
+}
+
+/// Reads blank line separated blocks of `key value` lines, one block per
+/// monster. Lines starting with `#` are ignored.
+pub fn parse_monsters(text: &str) -> Result<Vec<MonsterTemplate>, String> {
+    let mut monsters = Vec::new();
+    let lines: Vec<(usize, &str)> = text.lines().enumerate().filter(|(_, l)| !l.starts_with('#')).collect();
+    for block in lines.split(|(_, line)| line.trim().is_empty()).filter(|b| !b.is_empty()) {
+        let mut fields: Vec<(usize, &str, &str)> = Vec::new();
+        for (n, line) in block {
+            let (key, value) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            fields.push((n + 1, key, value.trim()));
+        }
+        let start = fields[0].0;
+        let get = |key: &str| {
+            fields
+                .iter()
+                .find(|(_, k, _)| *k == key)
+                .map(|(_, _, v)| *v)
+                .ok_or(format!("line {}: monster has no {}", start, key))
+        };
+        let number = |key: &str| get(key).and_then(|v| v.parse::<i32>().map_err(|_| format!("{} '{}' is not a number", key, v)));
+        let name = get("name")?.to_string();
+        let error = |what: String| format!("{}: {}", name, what);
+        let color: Vec<u8> = get("color")?.split_whitespace().filter_map(|c| c.parse().ok()).collect();
+        let damage = get("damage")?;
+        let mut monster = MonsterTemplate {
+            glyph: get("glyph")?.chars().next().ok_or(error("empty glyph".to_string()))?,
+            color: match color.as_slice() {
+                [r, g, b] => (*r, *g, *b),
+                _ => return Err(error("color must be three numbers".to_string())),
+            },
+            max_hp: number("hp").map_err(error)?,
+            armor_class: number("armor_class").map_err(error)?,
+            skill: number("skill").map_err(error)?,
+            damage: rltk::parse_dice_string(damage).map_err(|_| error(format!("bad damage dice '{}'", damage)))?,
+            xp: number("xp").map_err(error)?,
+            inflicts: None,
+            equipped: get("equipped").ok().map(str::to_string),
+            loot: Vec::new(),
+            name: name.clone(),
+        };
+        if let Ok(inflicts) = get("inflicts") {
+            let words: Vec<&str> = inflicts.split_whitespace().collect();
+            monster.inflicts = match words.as_slice() {
+                [kind, turns] => Some((
+                    parse_status(kind).ok_or(error(format!("unknown status '{}'", kind)))?,
+                    turns.parse().map_err(|_| error(format!("'{}' is not a number", turns)))?,
+                )),
+                _ => return Err(error("inflicts needs a status and a number of turns".to_string())),
+            };
+        }
+        for (_, _, loot) in fields.iter().filter(|(_, k, _)| *k == "loot") {
+            let (item, chance) = loot.rsplit_once(' ').ok_or(error(format!("bad loot '{}'", loot)))?;
+            let chance = chance.parse().map_err(|_| error(format!("'{}' is not a chance", chance)))?;
+            monster.loot.push((item.to_string(), chance));
+        }
+        monsters.push(monster);
+    }
+    Ok(monsters)
+}
+
+pub const ITEMS: &[ItemTemplate] = &[
+    ItemTemplate {
+        name: "Dagger",
+        glyph: '/',
+        color: rltk::CYAN,
+        melee_damage: Some("1d4+1"),
+        food: false,
+        healing: None,
+    },
+    ItemTemplate {
+        name: "Sword",
+        glyph: '/',
+        color: rltk::CYAN,
+        melee_damage: Some("1d8"),
+        food: false,
+        healing: None,
+    },
+    ItemTemplate {
+        name: "Health Potion",
+        glyph: '!',
+        color: rltk::MAGENTA,
+        melee_damage: None,
+        food: false,
+        healing: Some(8),
+    },
+    ItemTemplate {
+        name: "Ration",
+        glyph: '%',
+        color: rltk::GREEN,
+        melee_damage: None,
+        food: true,
+        healing: None,
+    },
+];
+
+pub const TRAPS: &[TrapTemplate] = &[
+    TrapTemplate {
+        name: "Spike Trap",
+        damage: Some("1d6"),
+        inflicts: None,
+        teleports: false,
+    },
+    TrapTemplate {
+        name: "Bear Trap",
+        damage: Some("1d4"),
+        inflicts: Some((StatusEffectKind::Stun, 3)),
+        teleports: false,
+    },
+    TrapTemplate {
+        name: "Gas Trap",
+        damage: None,
+        inflicts: Some((StatusEffectKind::Confusion, 4)),
+        teleports: false,
+    },
+    TrapTemplate {
+        name: "Teleport Trap",
+        damage: None,
+        inflicts: None,
+        teleports: true,
+    },
+];
+
+pub fn monster_template(name: &str) -> Option<&'static MonsterTemplate> {
+    monsters().iter().find(|m| m.name.eq_ignore_ascii_case(name))
+}
+
+pub fn spawn_monster(ecs: &mut World, template: &'static MonsterTemplate, x: i32, y: i32) -> Entity {
+    let mut builder = ecs
+        .create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437(template.glyph),
+            fg: RGB::named(template.color),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 1,
+        })
+        .with(Viewshed::new(8))
+        .with(Enemy {})
+        .with(BlocksTile {})
+        .with(CombatStats {
+            max_hp: template.max_hp,
+            hp: template.max_hp,
+            armor_class: template.armor_class,
+            skill: template.skill,
+            damage: template.damage,
+        })
+        .with(Name { name: template.name.clone() })
+        .with(ExperienceValue { xp: template.xp })
+        .with(LootTable { drops: &template.loot });
+    if let Some((kind, turns)) = template.inflicts {
+        builder = builder.with(InflictsStatus { kind, turns });
+    }
+    let monster = builder.build();
+    if let Some(weapon) = template.equipped.as_deref().and_then(item_template) {
+        let weapon = build_item(ecs, weapon).build();
+        ecs.write_storage::<Equipped>()
+            .insert(weapon, Equipped { owner: monster })
+            .expect("Unable to equip monster");
+    }
+    monster
+}
+
+pub fn item_template(name: &str) -> Option<&'static ItemTemplate> {
+    ITEMS.iter().find(|i| i.name.eq_ignore_ascii_case(name))
+}
+
+fn build_item<'a>(ecs: &'a mut World, template: &ItemTemplate) -> EntityBuilder<'a> {
+    let mut builder = ecs
+        .create_entity()
+        .with(Renderable {
+            glyph: rltk::to_cp437(template.glyph),
+            fg: RGB::named(template.color),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name { name: template.name.to_string() })
+        .with(Item {});
+    if let Some(damage) = template.melee_damage {
+        let damage = rltk::parse_dice_string(damage).expect("Invalid weapon damage dice");
+        builder = builder.with(MeleeWeapon { damage });
+    }
+    if template.food {
+        builder = builder.with(ProvidesFood {});
+    }
+    if let Some(heal_amount) = template.healing {
+        builder = builder.with(ProvidesHealing { heal_amount });
+    }
+    builder
+}
+
+pub fn spawn_item(ecs: &mut World, template: &ItemTemplate, x: i32, y: i32) -> Entity {
+    build_item(ecs, template).with(Position { x, y }).build()
+}
+
+/// Spawns whichever monster or item is called `name`.
+pub fn spawn_named(ecs: &mut World, name: &str, x: i32, y: i32) -> Option<Entity> {
+    if let Some(template) = monster_template(name) {
+        Some(spawn_monster(ecs, template, x, y))
+    } else {
+        item_template(name).map(|template| spawn_item(ecs, template, x, y))
+    }
+}
+
+/// Spawns everything the current map's generator asked for.
+pub fn spawn_map_markers(ecs: &mut World) {
+    let spawns = std::mem::take(&mut ecs.fetch_mut::<Map>().spawns);
+    for ((x, y), name) in spawns {
+        spawn_named(ecs, name, x, y).expect("Map asked for an unknown spawn");
+    }
+}
+
+/// `preferred` if it can be walked on, otherwise anywhere in `room` that
+/// can, if there is such a tile.
+pub fn spawn_point(map: &Map, rng: &mut RNG, room: &Rect, preferred: (i32, i32)) -> Option<(i32, i32)> {
+    if map.tiles[map.xy_idx(preferred.0, preferred.1)].is_walkable() {
+        Some(preferred)
+    } else {
+        rng.random_slice_entry(&map.walkable_in(room)).copied()
+    }
+}
+
+/// Fills `map`'s rooms: a goblin in each but `player_room`, a brazier and a
+/// trap in every room, and a ration in about a third of them.
+pub fn populate_rooms(ecs: &mut World, rng: &mut RNG, map: &Map, player_room: usize) {
+    // enemy
+    for (idx, room) in map.rooms.iter().enumerate() {
+        if idx != player_room {
+            if let Some((x, y)) = spawn_point(map, rng, room, room.center()) {
+                spawn_monster(ecs, monster_template("Goblin").unwrap(), x, y);
+            }
+        }
+    }
+    // braziers
+    for room in map.rooms.iter() {
+        if let Some((x, y)) = spawn_point(map, rng, room, (room.x1 + 1, room.y1 + 1)) {
+            spawn_brazier(ecs, x, y);
+        }
+    }
+    // traps
+    for room in map.rooms.iter() {
+        if let Some((x, y)) = rng.random_slice_entry(&map.walkable_in(room)) {
+            let trap = rng.random_slice_entry(TRAPS).unwrap();
+            spawn_trap(ecs, trap, *x, *y);
+        }
+    }
+    // food
+    for room in map.rooms.iter() {
+        if rng.roll_dice(1, 3) == 1 {
+            if let Some((x, y)) = spawn_point(map, rng, room, (room.x2, room.y2)) {
+                spawn_item(ecs, item_template("Ration").unwrap(), x, y);
+            }
+        }
+    }
+}
+
+/// Leaves a non-blocking corpse that rots away after `decay` turns.
+pub fn spawn_corpse(ecs: &mut World, name: &str, x: i32, y: i32, decay: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('%'),
+            fg: RGB::named(rltk::DARK_RED),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 3,
+        })
+        .with(Name { name: format!("{} corpse", name) })
+        .with(Corpse { decay })
+        .build()
+}
+
+
+/// Traps start out hidden until the player spots them or something walks in.
+pub fn spawn_trap(ecs: &mut World, template: &TrapTemplate, x: i32, y: i32) -> Entity {
+    let mut builder = ecs
+        .create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('^'),
+            fg: RGB::named(rltk::ORANGE),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name { name: template.name.to_string() })
+        .with(EntryTrigger {})
+        .with(Hidden {});
+    if let Some(damage) = template.damage {
+        let damage = rltk::parse_dice_string(damage).expect("Invalid trap damage dice");
+        builder = builder.with(InflictsDamage { damage });
+    }
+    if let Some((kind, turns)) = template.inflicts {
+        builder = builder.with(InflictsStatus { kind, turns });
+    }
+    if template.teleports {
+        builder = builder.with(Teleports {});
+    }
+    builder.build()
+}
+
+pub fn spawn_brazier(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('☼'),
+            fg: RGB::named(rltk::ORANGE),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 1,
+        })
+        .with(Name { name: "Brazier".to_string() })
+        .with(BlocksTile {})
+        .with(LightSource { radius: 7, color: RGB::from_f32(1.0, 0.6, 0.3) })
+        .build()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monster_data_loads() {
+        let xp: Vec<(&str, i32)> = monsters().iter().map(|m| (m.name.as_str(), m.xp)).collect();
+        assert_eq!(xp, vec![("Rat", 5), ("Goblin", 10), ("Orc", 25)]);
+        for monster in monsters() {
+            for item in monster.loot.iter().map(|(item, _)| item).chain(monster.equipped.iter()) {
+                assert!(item_template(item).is_some(), "{} carries unknown item {}", monster.name, item);
+            }
+        }
+    }
+
+    #[test]
+    fn bad_monster_data_is_rejected() {
+        let missing_xp = "name Bat\nglyph b\ncolor 1 2 3\nhp 2\narmor_class 9\nskill 0\ndamage 1d2\n";
+        assert!(parse_monsters(missing_xp).err().unwrap().contains("xp"));
+        let bad_dice = missing_xp.replace("1d2", "lots") + "xp 1\n";
+        assert!(parse_monsters(&bad_dice).err().unwrap().contains("dice"));
+    }
+}
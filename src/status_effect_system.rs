@@ -0,0 +1,88 @@
+use crate::{CombatStats, GameLog, Name, StatusEffect, StatusEffectKind, SufferDamage};
+use rltk::RandomNumberGenerator as RNG;
+use specs::prelude::*;
+
+pub struct StatusEffectSystem {}
+
+impl<'a> System<'a> for StatusEffectSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, StatusEffect>,
+        WriteStorage<'a, CombatStats>,
+        WriteStorage<'a, SufferDamage>,
+        ReadStorage<'a, Name>,
+        WriteExpect<'a, GameLog>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut effects, mut combat_stats, mut inflict_damage, names, mut log) = data;
+
+        for (effect_entity, effect) in (&entities, &mut effects).join() {
+            if !entities.is_alive(effect.target) {
+                entities.delete(effect_entity).expect("Unable to delete effect");
+                continue;
+            }
+            if let Some(stats) = combat_stats.get_mut(effect.target) {
+                if !effect.applied {
+                    stats.armor_class += effect.kind.armor_class_modifier();
+                    stats.skill += effect.kind.skill_modifier();
+                    effect.applied = true;
+                }
+                match effect.kind {
+                    StatusEffectKind::Poison => {
+                        SufferDamage::new_damage(&mut inflict_damage, effect.target, 1, None)
+                    }
+                    StatusEffectKind::Regeneration => {
+                        stats.hp = i32::min(stats.max_hp, stats.hp + 1)
+                    }
+                    _ => {}
+                }
+            }
+
+            effect.turns -= 1;
+            if effect.turns <= 0 {
+                if let Some(stats) = combat_stats.get_mut(effect.target) {
+                    if effect.applied {
+                        stats.armor_class -= effect.kind.armor_class_modifier();
+                        stats.skill -= effect.kind.skill_modifier();
+                    }
+                }
+                if let Some(name) = names.get(effect.target) {
+                    log.log(format!("{} is no longer {}.", name.name, effect.kind.adjective()));
+                }
+                entities.delete(effect_entity).expect("Unable to delete effect");
+            }
+        }
+    }
+}
+
+/// Puts `kind` on `target` for `turns` turns. An effect of the same kind
+/// that is already running is extended rather than stacked.
+pub fn inflict_status(
+    entities: &Entities,
+    effects: &mut WriteStorage<StatusEffect>,
+    target: Entity,
+    kind: StatusEffectKind,
+    turns: i32,
+) {
+    for effect in effects.join() {
+        if effect.target == target && effect.kind == kind {
+            effect.turns = i32::max(effect.turns, turns);
+            return;
+        }
+    }
+    entities
+        .build_entity()
+        .with(StatusEffect { target, kind, turns, applied: false }, effects)
+        .build();
+}
+
+/// Picks the direction a confused creature stumbles in instead of the one it wanted.
+pub fn confused_step(rng: &mut RNG) -> (i32, i32) {
+    match rng.range(0, 4) {
+        0 => (-1, 0),
+        1 => (1, 0),
+        2 => (0, -1),
+        _ => (0, 1),
+    }
+}
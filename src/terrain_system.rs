@@ -0,0 +1,31 @@
+use crate::{CombatStats, GameLog, Map, Player, Position, SufferDamage};
+use specs::prelude::*;
+
+/// Burns anything with hp that ends its turn on damaging terrain.
+pub struct TerrainDamageSystem {}
+
+impl<'a> System<'a> for TerrainDamageSystem {
+    type SystemData = (
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, Player>,
+        WriteStorage<'a, SufferDamage>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (map, mut log, entities, positions, combat_stats, players, mut suffer_damage) = data;
+
+        for (entity, pos, _stats) in (&entities, &positions, &combat_stats).join() {
+            let damage = map.tiles[map.xy_idx(pos.x, pos.y)].damage();
+            if damage > 0 {
+                SufferDamage::new_damage(&mut suffer_damage, entity, damage, None);
+                if players.get(entity).is_some() {
+                    log.log(format!("The ground burns you for {} hp!", damage));
+                }
+            }
+        }
+    }
+}
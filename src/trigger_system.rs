@@ -0,0 +1,105 @@
+use crate::status_effect_system::inflict_status;
+use crate::{
+    EntityMoved, EntryTrigger, GameLog, Hidden, InflictsDamage, InflictsStatus, Map, Name, Player,
+    Position, StatusEffect, SufferDamage, Teleports, TileType, Viewshed,
+};
+use rltk::{Point, RandomNumberGenerator as RNG};
+use specs::prelude::*;
+
+pub struct TriggerSystem {}
+
+impl<'a> System<'a> for TriggerSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        WriteExpect<'a, Map>,
+        WriteExpect<'a, Point>,
+        WriteExpect<'a, RNG>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        WriteStorage<'a, EntityMoved>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Viewshed>,
+        ReadStorage<'a, EntryTrigger>,
+        WriteStorage<'a, Hidden>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, InflictsDamage>,
+        ReadStorage<'a, InflictsStatus>,
+        ReadStorage<'a, Teleports>,
+        WriteStorage<'a, SufferDamage>,
+        WriteStorage<'a, StatusEffect>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            mut map,
+            mut player_pos,
+            mut rng,
+            mut log,
+            entities,
+            mut moved,
+            mut positions,
+            mut viewsheds,
+            triggers,
+            mut hidden,
+            names,
+            players,
+            inflicts_damage,
+            inflicts_status,
+            teleports,
+            mut suffer_damage,
+            mut status_effects,
+        ) = data;
+
+        let mut teleported: Vec<Entity> = Vec::new();
+        for (victim, _moved) in (&entities, &moved).join() {
+            let pos = match positions.get(victim) {
+                Some(pos) => pos,
+                None => continue,
+            };
+            let idx = map.xy_idx(pos.x, pos.y);
+            for trap in map.entities_at(idx).iter() {
+                if *trap == victim || triggers.get(*trap).is_none() {
+                    continue;
+                }
+                let victim_name = names.get(victim).map_or("Something", |n| n.name.as_str());
+                let trap_name = names.get(*trap).map_or("trap", |n| n.name.as_str());
+                if map.visible_tiles[idx] {
+                    log.log(format!("{} triggers a {}!", victim_name, trap_name));
+                    hidden.remove(*trap);
+                }
+
+                if let Some(damage) = inflicts_damage.get(*trap) {
+                    let amount = rng.roll(damage.damage);
+                    SufferDamage::new_damage(&mut suffer_damage, victim, amount, None);
+                }
+                if let Some(inflicts) = inflicts_status.get(*trap) {
+                    inflict_status(&entities, &mut status_effects, victim, inflicts.kind, inflicts.turns);
+                }
+                if teleports.get(*trap).is_some() {
+                    teleported.push(victim);
+                }
+            }
+        }
+        moved.clear();
+
+        for victim in teleported {
+            let floor: Vec<usize> = (0..map.tiles.len())
+                .filter(|idx| map.tiles[*idx] == TileType::Floor && !map.is_blocked(*idx))
+                .collect();
+            if let (Some(idx), Some(pos)) = (rng.random_slice_entry(&floor), positions.get_mut(victim)) {
+                pos.x = *idx as i32 % map.width;
+                pos.y = *idx as i32 / map.width;
+                map.move_entity(victim, *idx);
+                if players.get(victim).is_some() {
+                    player_pos.x = pos.x;
+                    player_pos.y = pos.y;
+                    log.log("You are yanked somewhere else!");
+                }
+                if let Some(viewshed) = viewsheds.get_mut(victim) {
+                    viewshed.dirty = true;
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,66 @@
-use rltk::{ RGB, Rltk, Console };
+use crate::{CombatStats, Experience, GameLog, HungerClock, HungerState, Player, StatusEffect};
+use rltk::{ RGB, Rltk };
 use specs::prelude::*;
 
+/// Rows the panel at the bottom of the screen takes up.
+const PANEL_HEIGHT: i32 = 7;
+
 pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
-    ctx.draw_box(0, 43, 79, 6, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK))
-}
\ No newline at end of file
+    let (width, height) = ctx.get_char_size();
+    let (width, height) = (width as i32, height as i32);
+    let top = height - PANEL_HEIGHT;
+    ctx.draw_box(0, top, width - 1, PANEL_HEIGHT - 1, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let players = ecs.read_storage::<Player>();
+    let status_effects = ecs.read_storage::<StatusEffect>();
+    let entities = ecs.entities();
+    for (entity, _player, stats) in (&entities, &players, &combat_stats).join() {
+        let health = format!(" HP: {} / {} ", stats.hp, stats.max_hp);
+        ctx.print_color(12, top, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), &health);
+        ctx.draw_bar_horizontal(28, top, 30, stats.hp, stats.max_hp, RGB::named(rltk::RED), RGB::named(rltk::BLACK));
+
+        for (x, effect) in (2..).zip(status_effects.join().filter(|e| e.target == entity)) {
+            ctx.set(x, top, effect.kind.color(), RGB::named(rltk::BLACK), rltk::to_cp437(effect.kind.glyph()));
+        }
+    }
+
+    let experience = ecs.read_storage::<Experience>();
+    for (_player, exp) in (&players, &experience).join() {
+        let level = format!(" Lvl {} ({}/{}) ", exp.level, exp.xp, Experience::threshold(exp.level + 1));
+        ctx.print_color(60, top, RGB::named(rltk::CYAN), RGB::named(rltk::BLACK), &level);
+    }
+
+    let clocks = ecs.read_storage::<HungerClock>();
+    for (_player, clock) in (&players, &clocks).join() {
+        let black = RGB::named(rltk::BLACK);
+        match clock.state {
+            HungerState::WellFed => ctx.print_color(width - 12, height - 1, RGB::named(rltk::GREEN), black, " Well Fed "),
+            HungerState::Normal => {}
+            HungerState::Hungry => ctx.print_color(width - 12, height - 1, RGB::named(rltk::ORANGE), black, " Hungry "),
+            HungerState::Starving => ctx.print_color(width - 12, height - 1, RGB::named(rltk::RED), black, " Starving "),
+        }
+    }
+
+    let log = ecs.fetch::<GameLog>();
+    for (y, entry) in (top + 1..height - 1).zip(log.entries.iter().rev()) {
+        ctx.print(2, y, entry);
+    }
+}
+
+pub fn draw_level_up(ecs: &World, ctx: &mut Rltk) {
+    let players = ecs.read_storage::<Player>();
+    let experience = ecs.read_storage::<Experience>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+
+    for (_player, exp, stats) in (&players, &experience, &combat_stats).join() {
+        let white = RGB::named(rltk::WHITE);
+        let black = RGB::named(rltk::BLACK);
+        let yellow = RGB::named(rltk::YELLOW);
+        ctx.draw_box(20, 15, 40, 8, white, black);
+        ctx.print_color(22, 15, yellow, black, format!(" Level {}! Choose a stat ({} left) ", exp.level, exp.stat_points));
+        ctx.print(22, 17, format!("(a) Armor class  {} -> {}", stats.armor_class, stats.armor_class + 1));
+        ctx.print(22, 18, format!("(b) Skill        {} -> {}", stats.skill, stats.skill + 1));
+        ctx.print(22, 19, format!("(c) Max hp       {} -> {}", stats.max_hp, stats.max_hp + 5));
+    }
+}
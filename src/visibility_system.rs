@@ -1,41 +1,107 @@
+use crate::fov::field_of_view;
 use crate::map::*;
-use crate::{Player, Position, Viewshed};
-use rltk::{field_of_view, Point};
+use crate::{CombatStats, Enemy, GameLog, Hidden, Name, Player, Position, Viewshed};
+use rltk::{Point, RandomNumberGenerator as RNG};
 use specs::prelude::*;
 
+/// 1d20 + skill has to beat this for the player to notice a hidden entity in view.
+const PERCEPTION_DC: i32 = 15;
+
 pub struct VisibilitySystem {}
 impl<'a> System<'a> for VisibilitySystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = (
         WriteExpect<'a, Map>,
         Entities<'a>,
         WriteStorage<'a, Viewshed>,
         WriteStorage<'a, Position>,
         ReadStorage<'a, Player>,
+        WriteStorage<'a, Hidden>,
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, Name>,
+        WriteExpect<'a, RNG>,
+        WriteExpect<'a, GameLog>,
+        ReadStorage<'a, Enemy>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, entities, mut viewshed, pos, player) = data;
+        let (mut map, entities, mut viewshed, pos, player, mut hidden, combat_stats, names, mut rng, mut log, enemies) = data;
         for (ent, viewshed, pos) in (&entities, &mut viewshed, &pos).join() {
-            if viewshed.dirty {
+            // Monsters only see lit tiles, and lights move on their own, so
+            // their viewsheds are redone every turn
+            let enemy = enemies.contains(ent);
+            let recomputed = viewshed.dirty || enemy;
+            if recomputed {
                 viewshed.dirty = false;
                 viewshed.visible_tiles.clear();
                 viewshed.visible_tiles =
-                    field_of_view(Point::new(pos.x, pos.y), viewshed.range, &*map);
+                    field_of_view(viewshed.algorithm, Point::new(pos.x, pos.y), viewshed.range, &*map);
                 viewshed.visible_tiles.retain(|p| {
                     p.x >= 0 && p.x < map.width - 1 && p.y >= 0 && p.y < map.height - 1
                 });
-                let _p: Option<&Player> = player.get(ent);
-                if let Some(_p) = _p {
-                    for t in map.visible_tiles.iter_mut() {
-                        *t = false
+                if enemy {
+                    viewshed.visible_tiles.retain(|p| map.is_lit(map.xy_idx(p.x, p.y)));
+                }
+            }
+            let _p: Option<&Player> = player.get(ent);
+            if let Some(_p) = _p {
+                // Lights move independently of the player, so what is lit
+                // inside the viewshed is refreshed every turn
+                for t in map.visible_tiles.iter_mut() {
+                    *t = false
+                }
+                for vis in viewshed.visible_tiles.iter() {
+                    let idx = map.xy_idx(vis.x, vis.y);
+                    if !map.is_lit(idx) {
+                        continue;
+                    }
+                    map.revealed_tiles[idx] = true;
+                    map.visible_tiles[idx] = true;
+                    if !recomputed {
+                        continue;
                     }
-                    for vis in viewshed.visible_tiles.iter() {
-                        let idx = map.xy_idx(vis.x, vis.y);
-                        map.revealed_tiles[idx] = true;
-                        map.visible_tiles[idx] = true;
+                    let skill = combat_stats.get(ent).map_or(0, |s| s.skill);
+                    for e in map.entities_at(idx).iter() {
+                        if hidden.get(*e).is_some() && rng.roll_dice(1, 20) + skill >= PERCEPTION_DC {
+                            if let Some(name) = names.get(*e) {
+                                log.log(format!("You spot a {}.", name.name));
+                            }
+                            hidden.remove(*e);
+                        }
                     }
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monsters_only_see_lit_tiles() {
+        let mut ecs = World::new();
+        ecs.register::<Viewshed>();
+        ecs.register::<Position>();
+        ecs.register::<Player>();
+        ecs.register::<Hidden>();
+        ecs.register::<CombatStats>();
+        ecs.register::<Name>();
+        ecs.register::<Enemy>();
+        ecs.insert(RNG::seeded(1));
+        ecs.insert(GameLog { entries: Vec::new() });
+        let mut map = Map::filled(20, 20, TileType::Floor);
+        let lit = map.xy_idx(8, 5);
+        map.light[lit] = rltk::RGB::from_f32(1.0, 1.0, 1.0);
+        ecs.insert(map);
+        let monster = ecs.create_entity().with(Position { x: 5, y: 5 }).with(Viewshed::new(8)).with(Enemy {}).build();
+
+        VisibilitySystem {}.run_now(&ecs);
+        assert_eq!(ecs.read_storage::<Viewshed>().get(monster).unwrap().visible_tiles, vec![Point::new(8, 5)]);
+
+        ecs.fetch_mut::<Map>().light[lit] = rltk::RGB::from_f32(0.0, 0.0, 0.0);
+        VisibilitySystem {}.run_now(&ecs);
+        assert!(ecs.read_storage::<Viewshed>().get(monster).unwrap().visible_tiles.is_empty());
+    }
+}
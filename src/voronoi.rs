@@ -0,0 +1,75 @@
+use crate::{Map, TileType};
+use rltk::RandomNumberGenerator as RNG;
+use std::collections::HashMap;
+
+const CELLS: usize = 24;
+
+impl Map {
+    /// A map cut into Voronoi cells around random seed points. Tiles where
+    /// one cell meets another become wall, with one opening knocked through
+    /// wherever two cells share a border.
+    pub fn voronoi_map(seed: u64) -> Map {
+        let mut rng = RNG::seeded(seed);
+        let mut map = Map::filled(80, 50, TileType::Floor);
+        map.seed = seed;
+        let points: Vec<(i32, i32)> =
+            (0..CELLS).map(|_| (rng.range(1, map.width - 1), rng.range(1, map.height - 1))).collect();
+        let cell: Vec<usize> = (0..map.tiles.len())
+            .map(|idx| {
+                let (x, y) = (idx as i32 % map.width, idx as i32 / map.width);
+                (0..CELLS)
+                    .min_by_key(|c| {
+                        let (px, py) = points[*c];
+                        (px - x).pow(2) + (py - y).pow(2)
+                    })
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        for idx in 0..map.tiles.len() {
+            let (x, y) = (idx as i32 % map.width, idx as i32 / map.width);
+            let right = x < map.width - 1 && cell[idx + 1] != cell[idx];
+            let below = y < map.height - 1 && cell[idx + map.width as usize] != cell[idx];
+            if right || below {
+                map.tiles[idx] = TileType::Wall;
+            }
+        }
+        map.wall_border();
+
+        // Wall tiles with floor of two different cells on opposite sides
+        let mut openings: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for y in 1..map.height - 1 {
+            for x in 1..map.width - 1 {
+                let idx = map.xy_idx(x, y);
+                if map.tiles[idx] != TileType::Wall {
+                    continue;
+                }
+                for (a, b) in [(idx - 1, idx + 1), (idx - map.width as usize, idx + map.width as usize)].iter() {
+                    let floor = map.tiles[*a] == TileType::Floor && map.tiles[*b] == TileType::Floor;
+                    if floor && cell[*a] != cell[*b] {
+                        let pair = (cell[*a].min(cell[*b]), cell[*a].max(cell[*b]));
+                        openings.entry(pair).or_default().push(idx);
+                    }
+                }
+            }
+        }
+        let mut pairs: Vec<_> = openings.into_iter().collect();
+        pairs.sort();
+        for (_, candidates) in pairs {
+            if let Some(idx) = rng.random_slice_entry(&candidates) {
+                map.tiles[*idx] = TileType::Floor;
+            }
+        }
+        map.connect_regions();
+
+        let mut regions = vec![Vec::new(); CELLS];
+        for idx in 0..map.tiles.len() {
+            if map.tiles[idx] == TileType::Floor {
+                regions[cell[idx]].push(idx);
+            }
+        }
+        regions.sort_by_key(|r| std::cmp::Reverse(r.len()));
+        map.rooms_from_regions(&regions, 1);
+        map
+    }
+}
@@ -0,0 +1,209 @@
+use crate::map_ascii;
+use crate::{Map, Rect, TileType};
+use rltk::RandomNumberGenerator as RNG;
+use std::collections::HashMap;
+
+/// Side of the square patterns cut from the sample.
+const N: i32 = 3;
+const MAX_ATTEMPTS: u32 = 20;
+const DIRECTIONS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+type Pattern = Vec<TileType>;
+
+/// The overlapping wave function collapse model: every NxN window of the
+/// sample is a pattern, and two patterns may sit side by side wherever they
+/// agree on the tiles they share.
+struct Model {
+    patterns: Vec<Pattern>,
+    weights: Vec<u32>,
+    /// `compatible[p][d]` lists the patterns allowed one step in
+    /// `DIRECTIONS[d]` from pattern `p`
+    compatible: Vec<[Vec<usize>; 4]>,
+}
+
+impl Model {
+    fn learn(sample: &Map) -> Model {
+        let mut counts: HashMap<Pattern, u32> = HashMap::new();
+        let mut order = Vec::new();
+        for y in 0..=sample.height - N {
+            for x in 0..=sample.width - N {
+                let pattern: Pattern = (0..N * N)
+                    .map(|i| sample.tiles[sample.xy_idx(x + i % N, y + i / N)])
+                    .collect();
+                let count = counts.entry(pattern.clone()).or_insert(0);
+                if *count == 0 {
+                    order.push(pattern);
+                }
+                *count += 1;
+            }
+        }
+        let weights = order.iter().map(|p| counts[p]).collect();
+        let compatible = order
+            .iter()
+            .map(|a| {
+                let mut allowed: [Vec<usize>; 4] = Default::default();
+                for (d, (dx, dy)) in DIRECTIONS.iter().enumerate() {
+                    allowed[d] = (0..order.len()).filter(|b| agrees(a, &order[*b], *dx, *dy)).collect();
+                }
+                allowed
+            })
+            .collect();
+        Model { patterns: order, weights, compatible }
+    }
+
+    /// Collapses a `width` by `height` grid of tiles, or None on a contradiction.
+    fn run(&self, width: i32, height: i32, rng: &mut RNG) -> Option<Vec<TileType>> {
+        let mut wave = Wave::new(self, width - N + 1, height - N + 1);
+        let cells = wave.remaining.len();
+        // Breaks ties between equally undecided cells
+        let noise: Vec<u32> = (0..cells).map(|_| rng.range(0, 1 << 16) as u32).collect();
+
+        while let Some(cell) = (0..cells).filter(|c| wave.remaining[*c] > 1).min_by_key(|c| (wave.remaining[*c], noise[*c])) {
+            let options: Vec<usize> = wave.options(cell).collect();
+            let total: u32 = options.iter().map(|p| self.weights[*p]).sum();
+            let mut roll = rng.range(0, total as i32) as u32;
+            let chosen = *options
+                .iter()
+                .find(|p| {
+                    if roll < self.weights[**p] {
+                        true
+                    } else {
+                        roll -= self.weights[**p];
+                        false
+                    }
+                })
+                .unwrap_or(&options[0]);
+            for p in options.into_iter().filter(|p| *p != chosen) {
+                wave.ban(cell, p);
+            }
+            if !wave.propagate(self) {
+                return None;
+            }
+        }
+
+        let (cw, ch) = (wave.width, wave.height);
+        let mut tiles = vec![TileType::Wall; (width * height) as usize];
+        for y in 0..height {
+            for x in 0..width {
+                let (cx, cy) = (x.min(cw - 1), y.min(ch - 1));
+                let pattern = wave.options((cy * cw + cx) as usize).next()?;
+                tiles[(y * width + x) as usize] = self.patterns[pattern][((y - cy) * N + x - cx) as usize];
+            }
+        }
+        Some(tiles)
+    }
+}
+
+/// The patterns each cell could still become.
+struct Wave {
+    width: i32,
+    height: i32,
+    patterns: usize,
+    /// Whether pattern `p` is still possible in `cell`, at `cell * patterns + p`
+    possible: Vec<bool>,
+    remaining: Vec<usize>,
+    /// For each cell and pattern, how many of the patterns still possible in
+    /// the neighbour in each direction allow it. It's ruled out at zero.
+    support: Vec<[u32; 4]>,
+    /// Bans not yet passed on to the neighbours.
+    banned: Vec<(usize, usize)>,
+}
+
+impl Wave {
+    fn new(model: &Model, width: i32, height: i32) -> Wave {
+        let cells = (width * height) as usize;
+        let patterns = model.patterns.len();
+        let initial: Vec<[u32; 4]> = model
+            .compatible
+            .iter()
+            .map(|allowed| {
+                let mut support = [0; 4];
+                for (d, count) in support.iter_mut().enumerate() {
+                    *count = allowed[d].len() as u32;
+                }
+                support
+            })
+            .collect();
+        Wave {
+            width,
+            height,
+            patterns,
+            possible: vec![true; cells * patterns],
+            remaining: vec![patterns; cells],
+            support: (0..cells).flat_map(|_| initial.iter().copied()).collect(),
+            banned: Vec::new(),
+        }
+    }
+
+    fn options(&self, cell: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..self.patterns).filter(move |p| self.possible[cell * self.patterns + p])
+    }
+
+    fn ban(&mut self, cell: usize, pattern: usize) {
+        self.possible[cell * self.patterns + pattern] = false;
+        self.remaining[cell] -= 1;
+        self.banned.push((cell, pattern));
+    }
+
+    /// Rules out everything the queued bans make impossible. Returns false
+    /// if some cell is left with no options at all.
+    fn propagate(&mut self, model: &Model) -> bool {
+        while let Some((cell, pattern)) = self.banned.pop() {
+            let (x, y) = (cell as i32 % self.width, cell as i32 / self.width);
+            for (d, (dx, dy)) in DIRECTIONS.iter().enumerate() {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || nx >= self.width || ny < 0 || ny >= self.height {
+                    continue;
+                }
+                let neighbour = (ny * self.width + nx) as usize;
+                // Directions come in opposite pairs
+                let back = d ^ 1;
+                for p in model.compatible[pattern][d].iter() {
+                    let idx = neighbour * self.patterns + p;
+                    self.support[idx][back] -= 1;
+                    if self.support[idx][back] == 0 && self.possible[idx] {
+                        self.ban(neighbour, *p);
+                        if self.remaining[neighbour] == 0 {
+                            return false;
+                        }
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Whether `b`, placed `(dx, dy)` away from `a`, matches it where they overlap.
+fn agrees(a: &[TileType], b: &[TileType], dx: i32, dy: i32) -> bool {
+    for y in dy.max(0)..(N + dy).min(N) {
+        for x in dx.max(0)..(N + dx).min(N) {
+            if a[(y * N + x) as usize] != b[((y - dy) * N + x - dx) as usize] {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+impl Map {
+    /// A map grown by wave function collapse from the crossroads fixture,
+    /// starting over whenever it paints itself into a corner. Falls back to
+    /// rooms and corridors if it never gets there.
+    pub fn wfc_map(seed: u64) -> Map {
+        let (_, sample) = map_ascii::FIXTURES.iter().find(|(name, _)| *name == "crossroads").expect("No WFC sample");
+        let sample = map_ascii::import(sample).expect("Bad WFC sample");
+        let model = Model::learn(&sample);
+        let mut rng = RNG::seeded(seed);
+        let mut map = Map::filled(80, 50, TileType::Wall);
+        map.seed = seed;
+        match (0..MAX_ATTEMPTS).find_map(|_| model.run(map.width, map.height, &mut rng)) {
+            Some(tiles) => map.tiles = tiles,
+            None => return Map::new_map(seed),
+        }
+        map.wall_border();
+        map.connect_regions();
+        map.rooms.push(Rect::new(0, 0, map.width - 2, map.height - 2));
+        map
+    }
+}